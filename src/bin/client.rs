@@ -1,135 +1,487 @@
-use std::{
-    io::{Read, Write},
-    net::TcpStream,
-};
+use std::{cell::RefCell, fs, io, path::PathBuf, process, rc::Rc};
 
 use clap::Parser;
-use ggez::{conf, event, ContextBuilder};
+use ggez::{
+    conf, event,
+    input::keyboard::{KeyCode, KeyInput},
+    ContextBuilder,
+};
 use reversi::{
-    available_captures,
+    book::Book,
     cli::Args,
-    gui::{Board, Move, PieceConfig, Player},
-    valid_move,
+    client::{find_game, ClockView, Link},
+    controller::{Move, Player},
+    game::{Action, GameState, PieceConfig, Side},
+    gui::{Board, BoardConfig},
+    notation::{replay, write_transcript},
+    protocol::Message,
 };
 
-struct Enemy {
-    stream: TcpStream,
+struct Connection {
+    link: Link,
+    // Every action of the current game including passes
+    moves: Vec<Action>,
+    // Position to switch to once the server's game turned out to differ
+    reset: Option<PieceConfig>,
+    // Set once the opponent resigned or the connection is gone
+    over: bool,
+    // None for untimed games
+    clock: Option<ClockView>,
+    // Side of the next game while waiting for it to start
+    seeking: Option<Side>,
 }
 
-impl Player for Enemy {
-    fn play_move(&mut self, _config: &PieceConfig) -> Move {
-        let position = loop {
-            let mut buf = [0; 8];
-            match self.stream.read(&mut buf) {
-                Ok(0) => continue,
-                Ok(_) => {
-                    let position = u64::from_le_bytes(buf);
-                    if position != 0 {
-                        break position;
+impl Connection {
+    fn connect(addr: String) -> io::Result<Connection> {
+        let name = concat!("reversi client ", env!("CARGO_PKG_VERSION"));
+        let link = Link::connect(addr, name)?;
+        println!("connected to {}", link.server);
+        Ok(Connection {
+            link,
+            moves: Vec::new(),
+            reset: None,
+            over: false,
+            clock: None,
+            seeking: None,
+        })
+    }
+
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.link.send(message)
+    }
+
+    // Waits for the next message
+    fn read(&mut self) -> io::Result<Message> {
+        self.link.read()
+    }
+
+    fn end(&mut self, reason: String) -> Move {
+        println!("{reason}");
+        self.over = true;
+        Move::Board
+    }
+
+    fn wait_for_game(&mut self) -> io::Result<Side> {
+        self.link
+            .wait_for_game(|id| println!("created game {id}, waiting for an opponent"))
+    }
+
+    // Catches up with the server after reconnecting, the server can only
+    // miss moves of ours that never arrived, including one still being sent
+    fn resume(&mut self, sending: Option<u64>) -> io::Result<()> {
+        let moves = self.link.reconnect()?;
+        println!("reconnected after {} moves", moves.len());
+        let mut played = self.moves.clone();
+        played.extend(sending.map(Action::Place));
+        if played.starts_with(&moves) {
+            for &action in &played[moves.len()..] {
+                if let Action::Place(position) = action {
+                    self.send(&Message::Move(position))?;
+                }
+            }
+            return Ok(());
+        }
+        let positions = replay(&GameState::new(), &moves)
+            .ok_or_else(|| io::Error::other("server sent an illegal game"))?;
+        self.reset = Some(positions.last().unwrap().config);
+        self.moves = moves;
+        Ok(())
+    }
+
+    // Asks for another game with the same side without waiting for it, the
+    // answers arrive through poll
+    fn seek(&mut self, side: Side) {
+        if self.seeking.is_some() {
+            return;
+        }
+        match self.send(&Message::ListGames) {
+            Ok(()) => {
+                println!("looking for a new game");
+                self.seeking = Some(side);
+            }
+            Err(error) => println!("cannot start a new game: {error}"),
+        }
+    }
+
+    // Goes on looking for a new game, the board is reset once it starts
+    fn poll_seeking(&mut self, side: Side) -> Option<Move> {
+        loop {
+            let message = self.link.try_read()?;
+            let request = match message {
+                Ok(Message::GameList(games)) => find_game(&games, side),
+                Ok(Message::GameCreated(id)) => {
+                    println!("created game {id}, waiting for an opponent");
+                    continue;
+                }
+                Ok(Message::ColorAssigned(_)) => {
+                    println!("new game started");
+                    self.seeking = None;
+                    self.over = false;
+                    self.moves.clear();
+                    self.clock = None;
+                    return Some(Move::Reset(GameState::new().config));
+                }
+                Ok(Message::Error(error)) => {
+                    println!("cannot start a new game: {error}");
+                    self.seeking = None;
+                    return None;
+                }
+                Ok(_) => continue,
+                Err(error) => {
+                    println!("connection lost: {error}");
+                    self.seeking = None;
+                    return None;
+                }
+            };
+            if let Err(error) = self.send(&request) {
+                println!("cannot start a new game: {error}");
+                self.seeking = None;
+                return None;
+            }
+        }
+    }
+
+    // Handles what the server sent since the last frame, returning the
+    // opponent's move or a change to the board once there is one
+    fn poll(&mut self, config: &PieceConfig) -> Option<Move> {
+        if let Some(config) = self.reset.take() {
+            return Some(Move::Reset(config));
+        }
+        if let Some(side) = self.seeking {
+            return self.poll_seeking(side).or(Some(Move::Board));
+        }
+        // Nothing left to receive once the game is over
+        if self.over || GameState::from(*config).is_terminal() {
+            return Some(Move::Board);
+        }
+        loop {
+            let message = self.link.try_read()?;
+            match message {
+                Ok(Message::Move(position)) => {
+                    // A move the board cannot play means the game is out of sync
+                    if GameState::from(*config).move_mask() & position == 0 {
+                        return Some(self.end("opponent sent an illegal move".to_string()));
+                    }
+                    return Some(Move::Position(position));
+                }
+                Ok(Message::Resign) => {
+                    return Some(self.end("game ended by resignation".to_string()))
+                }
+                Ok(Message::TimeOut(side)) => {
+                    return Some(self.end(format!("{side:?} lost on time")))
+                }
+                Ok(Message::Error(error)) => {
+                    return Some(self.end(format!("server error: {error}")))
+                }
+                Ok(Message::Clock {
+                    black,
+                    white,
+                    period,
+                }) => self.clock = Some(ClockView::new(black, white, period)),
+                Ok(Message::Paused { seconds }) => {
+                    println!("opponent disconnected, waiting up to {seconds} seconds");
+                    let side = GameState::from(*config).side_to_move();
+                    if let Some(clock) = &mut self.clock {
+                        clock.pause(side);
+                    }
+                }
+                Ok(Message::Unpaused) => println!("opponent reconnected"),
+                // Passes are played locally and pings need no answer
+                Ok(_) => {}
+                Err(error) => {
+                    println!("connection lost: {error}");
+                    if let Err(error) = self.resume(None) {
+                        return Some(self.end(format!("cannot resume game: {error}")));
+                    }
+                    if let Some(config) = self.reset.take() {
+                        return Some(Move::Reset(config));
                     }
                 }
-                _ => {}
             }
+        }
+    }
+
+    // Clocks shown next to the board, the side to move's one runs
+    fn status(&self, config: &PieceConfig) -> Vec<String> {
+        let Some(clock) = &self.clock else {
+            return Vec::new();
         };
-        Move::Position(position)
+        let state = GameState::from(*config);
+        let running = (!self.over && !state.is_terminal()).then(|| state.side_to_move());
+        [Side::Black, Side::White]
+            .iter()
+            .map(|&side| format!("{side:?} {}", clock.remaining(side, running)))
+            .collect()
+    }
+
+    // Sends a move of ours, resuming first if the connection is gone
+    fn send_move(&mut self, position: u64) {
+        if self.send(&Message::Move(position)).is_ok() {
+            return;
+        }
+        println!("connection lost");
+        if let Err(error) = self.resume(Some(position)) {
+            self.end(format!("cannot resume game: {error}"));
+        }
+    }
+}
+
+struct Enemy {
+    connection: Rc<RefCell<Connection>>,
+}
+
+impl Player for Enemy {
+    fn play_move(&mut self, config: &PieceConfig) -> Move {
+        let polled = self.connection.borrow_mut().poll(config);
+        polled.unwrap_or(Move::Pending)
     }
 
     fn enemy_move(&mut self, current_move: u64) {
-        let bytes = &current_move.to_le_bytes();
-        println!("sent buf: {:?}", bytes);
-        self.stream.write(bytes).unwrap();
+        self.connection.borrow_mut().send_move(current_move);
+    }
+}
+
+// Either side of a game being watched, nothing is ever sent back
+struct Spectated {
+    connection: Rc<RefCell<Connection>>,
+}
+
+impl Player for Spectated {
+    fn play_move(&mut self, config: &PieceConfig) -> Move {
+        let polled = self.connection.borrow_mut().poll(config);
+        polled.unwrap_or(Move::Pending)
     }
+
+    fn enemy_move(&mut self, _current_move: u64) {}
 }
 
-struct Ally {}
+// The player at this client, the server is still polled on its turn so
+// clocks and time outs show up
+struct Ally {
+    connection: Rc<RefCell<Connection>>,
+}
 
 impl Player for Ally {
-    fn play_move(&mut self, _config: &PieceConfig) -> Move {
-        Move::Board
+    fn play_move(&mut self, config: &PieceConfig) -> Move {
+        let polled = self.connection.borrow_mut().poll(config);
+        match polled {
+            // Only the opponent's moves are ever relayed
+            Some(Move::Position(_)) | None => Move::Board,
+            Some(played) => played,
+        }
     }
 
     fn enemy_move(&mut self, _current_move: u64) {}
 }
 
-fn capture(config: &PieceConfig, position: u64) -> PieceConfig {
-    let mut config = config.clone();
-    let (ally, foe) = config.ally_foe();
-    let mesh = available_captures(ally, foe).unwrap();
-    println!("{:?}", mesh);
-    let mesh = *mesh.get(&position).unwrap() | position;
-    if config.blacks_play {
-        config.white_pieces &= !mesh;
-        config.black_pieces |= mesh;
-    } else {
-        config.black_pieces &= !mesh;
-        config.white_pieces |= mesh;
+// Plays a move on the local board, player is false for spectators who
+// cannot start a game of their own afterwards. Moves are None for games
+// watched from midway, which have no transcript
+fn capture(
+    config: &PieceConfig,
+    position: u64,
+    mut moves: Option<&mut Vec<Action>>,
+    record: Option<&PathBuf>,
+    player: bool,
+) -> PieceConfig {
+    let mut state = GameState::from(*config)
+        .apply(Action::Place(position))
+        .expect("cannot capture on an invalid position");
+    let mut played = vec![Action::Place(position)];
+    // The server plays forced passes itself, the client mirrors them locally
+    if state.must_pass() {
+        state = state.apply(Action::Pass).unwrap();
+        played.push(Action::Pass);
+        println!("{:?} passes", state.side_to_move().opponent());
+    }
+    if let Some(moves) = moves.as_mut() {
+        moves.extend(played);
     }
-    config.blacks_play = !config.blacks_play;
-    config
+    if state.is_terminal() {
+        let (black, white) = state.score();
+        match state.winner() {
+            Some(side) => println!("game over {black}-{white}, {side:?} wins"),
+            None => println!("game over {black}-{white}, draw"),
+        }
+        if let Some(moves) = moves {
+            let transcript = write_transcript(moves);
+            println!("transcript: {transcript}");
+            if let Some(path) = record {
+                if let Err(error) = fs::write(path, transcript + "\n") {
+                    eprintln!("cannot write game record to {}: {error}", path.display());
+                }
+            }
+        }
+        if player {
+            println!("press N for a new game");
+        }
+    }
+    state.config
 }
 
 fn valid(config: &PieceConfig, position: u64) -> bool {
-    let (ally, foe) = config.ally_foe();
-    valid_move(ally, foe, position)
+    GameState::from(*config)
+        .apply(Action::Place(position))
+        .is_some()
 }
 
-fn main() {
-    let args = Args::parse();
-    let mut stream = TcpStream::connect(args.addr()).expect("cannot connect on given address");
-    println!("connection on {:?}", args.addr());
-
-    let mut buf = [0; 1024];
-    let read = stream.read(&mut buf).unwrap();
-    let mut response =
-        String::from_utf8(buf[0..read].to_vec()).expect("cannot convert response to string");
+// Looks for another game with the same side once the current one is over,
+// the window keeps running while waiting for an opponent
+fn new_game(connection: &RefCell<Connection>, side: Side, board: &BoardConfig) -> BoardConfig {
+    let mut connection = connection.borrow_mut();
+    if connection.over || GameState::from(board.piece_config).is_terminal() {
+        connection.seek(side);
+    }
+    board.clone()
+}
 
-    if response == "pick".to_string() {
-        if args.wants_black() {
-            stream.write(b"black").expect("cannot send pick response");
-        } else {
-            stream.write(b"white").expect("cannot send pick response");
-        };
+fn player_board(mut connection: Connection, args: &Args) -> Board {
+    let request = match args.join {
+        Some(id) => Message::JoinGame(id),
+        None if args.wants_black() => Message::CreateGame(Side::Black),
+        None => Message::CreateGame(Side::White),
+    };
+    connection.send(&request).expect("cannot request a game");
+    let side = connection.wait_for_game().expect("cannot start a game");
+    println!("playing: {side:?}");
 
-        let mut buf = [0; 1024];
-        let read = stream.read(&mut buf).unwrap();
-        response = String::from_utf8(buf[0..read].to_vec()).unwrap();
-    }
-    println!("playing: {}", response);
+    let connection = Rc::new(RefCell::new(connection));
+    let enemy = Enemy {
+        connection: connection.clone(),
+    };
+    let ally = Ally {
+        connection: connection.clone(),
+    };
+    let status = {
+        let connection = connection.clone();
+        move |config: &PieceConfig| connection.borrow().status(config)
+    };
+    let keypress = {
+        let connection = connection.clone();
+        move |input: KeyInput, config: &BoardConfig| match input.keycode {
+            Some(KeyCode::N) => new_game(&connection, side, config),
+            _ => config.clone(),
+        }
+    };
+    let capture = {
+        let connection = connection.clone();
+        let record = args.record.clone();
+        move |config: &PieceConfig, position| {
+            let moves = &mut connection.borrow_mut().moves;
+            capture(config, position, Some(moves), record.as_ref(), true)
+        }
+    };
+    let valid =
+        move |config: &PieceConfig, position| !connection.borrow().over && valid(config, position);
 
-    let board = if response == "black".to_string() {
+    let board = if side == Side::Black {
         Board::new(
             1600.0 / 8.0,
-            PieceConfig {
-                white_pieces: 34493956096,
-                black_pieces: 68987912192,
-                blacks_play: true,
-            },
-            Box::new(|_, config| config.clone()),
+            GameState::new().config,
+            Box::new(keypress),
             Box::new(capture),
             Box::new(valid),
-            Box::new(Ally {}),
-            Box::new(Enemy { stream }),
+            Box::new(ally),
+            Box::new(enemy),
         )
     } else {
         Board::new(
             1600.0 / 8.0,
-            PieceConfig {
-                white_pieces: 34493956096,
-                black_pieces: 68987912192,
-                blacks_play: true,
-            },
-            Box::new(|_, config| config.clone()),
+            GameState::new().config,
+            Box::new(keypress),
             Box::new(capture),
             Box::new(valid),
-            Box::new(Enemy { stream }),
-            Box::new(Ally {}),
+            Box::new(enemy),
+            Box::new(ally),
         )
     };
+    board.with_status(Box::new(status)).with_book(Book::new())
+}
+
+fn spectator_board(mut connection: Connection, id: u32) -> io::Result<Board> {
+    connection.send(&Message::Spectate(id))?;
+    let position = loop {
+        match connection.read()? {
+            Message::Position(position) => break position,
+            Message::Error(error) => return Err(io::Error::other(error)),
+            _ => {}
+        }
+    };
+    println!("watching game {id}");
+    // Only the position is sent, the moves before it are unknown
+    let from_start = position == GameState::new().config;
+
+    let connection = Rc::new(RefCell::new(connection));
+    let capture = {
+        let connection = connection.clone();
+        move |config: &PieceConfig, position| {
+            let moves = &mut connection.borrow_mut().moves;
+            capture(config, position, from_start.then_some(moves), None, false)
+        }
+    };
+    let status = {
+        let connection = connection.clone();
+        move |config: &PieceConfig| connection.borrow().status(config)
+    };
+    let board = Board::new(
+        1600.0 / 8.0,
+        position,
+        Box::new(|_, config| config.clone()),
+        Box::new(capture),
+        // Clicks never place a disc for either side
+        Box::new(|_, _| false),
+        Box::new(Spectated {
+            connection: connection.clone(),
+        }),
+        Box::new(Spectated { connection }),
+    )
+    .with_status(Box::new(status));
+    // Openings are named by the moves from the start
+    Ok(match from_start {
+        true => board.with_book(Book::new()),
+        false => board,
+    })
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut connection = Connection::connect(args.addr()).expect("cannot connect on given address");
+    println!("connection on {:?}", args.addr());
+
+    if args.list {
+        connection
+            .send(&Message::ListGames)
+            .expect("cannot list games");
+        loop {
+            let message = connection.read().expect("cannot list games");
+            if let Message::GameList(games) = message {
+                for game in games {
+                    match game.guest {
+                        Some(guest) => println!(
+                            "game {}: {} plays {:?} against {guest}",
+                            game.id, game.host, game.side
+                        ),
+                        None => println!("game {}: {} plays {:?}", game.id, game.host, game.side),
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    let board = match args.spectate {
+        Some(id) => spectator_board(connection, id).unwrap_or_else(|error| {
+            eprintln!("cannot spectate game {id}: {error}");
+            process::exit(1);
+        }),
+        None => player_board(connection, &args),
+    };
 
     let mut config = conf::Conf::new();
     config.window_setup.title = String::from("Reversi Client");
     config.window_mode.height = 1600.0;
-    config.window_mode.width = 1600.0;
+    // Room for the clocks next to the board
+    config.window_mode.width = 2000.0;
     let (context, event_loop) = ContextBuilder::new("Reversi", "Miyamizu")
         .default_conf(config)
         .build()
@@ -1,14 +1,21 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap, path::PathBuf, rc::Rc};
 
+use clap::Parser;
 use ggez::{
-    conf, event,
+    conf,
+    event::{self, MouseButton},
     graphics::Color,
-    input::keyboard::{KeyCode, KeyInput},
+    input::keyboard::{KeyCode, KeyInput, KeyMods},
     ContextBuilder, GameResult,
 };
 use reversi::{
-    available_captures,
-    gui::{Board, BoardConfig, Move, PieceConfig, Player},
+    book::Book,
+    cli::DebuggerArgs,
+    controller::{Move, Player},
+    game::{Action, GameState, PieceConfig, START_BLACK, START_WHITE},
+    gui::{Board, BoardConfig},
+    notation::{read_position, save_position, write_position},
+    stable_discs,
 };
 
 const BOARD_SIZE: f32 = 800.0 * 2.0;
@@ -17,78 +24,164 @@ struct Client {}
 
 impl Player for Client {
     fn play_move(&mut self, _config: &PieceConfig) -> Move {
-        return Move::Board;
+        Move::Board
     }
     fn enemy_move(&mut self, _current_move: u64) {}
 }
 
 fn main() -> GameResult {
+    let args = DebuggerArgs::parse();
+    let piece_config = if args.file.exists() {
+        read_position(&args.file).unwrap_or_else(|error| {
+            eprintln!("could not load {}: {error}", args.file.display());
+            GameState::new().config
+        })
+    } else {
+        GameState::new().config
+    };
+
+    // Clicks edit the position until switched to playing moves
+    let editing = Rc::new(Cell::new(true));
     let board = Board::new(
         BOARD_SIZE / 8.0,
-        PieceConfig {
-            /*
-            white_pieces: 34493956096,
-            black_pieces: 68987912192,
-            */
-            white_pieces: 0,
-            black_pieces: 0,
-            blacks_play: true,
-        },
-        Box::new(handler),
+        piece_config,
+        Box::new(handler(editing.clone(), args.file)),
         Box::new(capture),
-        Box::new(|_, _| true),
+        Box::new({
+            let editing = editing.clone();
+            move |config, position| {
+                !editing.get() && GameState::from(*config).move_mask() & position != 0
+            }
+        }),
         Box::new(Client {}),
         Box::new(Client {}),
-    );
+    )
+    .with_editor(Box::new({
+        let editing = editing.clone();
+        move |config, position, button| editing.get().then(|| edit(config, position, button))
+    }))
+    .with_status(Box::new(move |config| status(config, editing.get())))
+    .with_book(Book::new())
+    .with_move_list();
 
     let mut config = conf::Conf::new();
     config.window_setup.title = String::from("Reversi Debugger");
     config.window_mode.height = BOARD_SIZE;
-    config.window_mode.width = BOARD_SIZE;
+    // Room for the status and move list
+    config.window_mode.width = BOARD_SIZE * 1.5;
     let (context, event_loop) = ContextBuilder::new("Reversi", "Miyamizu")
         .default_conf(config)
         .build()?;
     event::run(context, event_loop, board);
 }
 
-fn handler(input: KeyInput, config: &BoardConfig) -> BoardConfig {
-    let mut config = config.clone();
-    match input.keycode {
-        Some(KeyCode::W) => config.piece_config.blacks_play = false,
-        Some(KeyCode::B) => config.piece_config.blacks_play = true,
-        Some(KeyCode::D) => config.mesh = HashMap::new(),
-        Some(KeyCode::R) => {
-            config.piece_config.white_pieces = 34493956096;
-            config.piece_config.black_pieces = 68987912192;
-            config.mesh = HashMap::new();
-        }
-        Some(KeyCode::S) => {
-            let (ally, foe) = config.piece_config.ally_foe();
-            if let Some(captures) = available_captures(ally, foe) {
-                let mut mesh: u64 = 0;
-                for capture in captures.keys() {
-                    mesh |= capture;
+fn handler(
+    editing: Rc<Cell<bool>>,
+    file: PathBuf,
+) -> impl Fn(KeyInput, &BoardConfig) -> BoardConfig {
+    move |input, config| {
+        let mut config = config.clone();
+        let ctrl = input.mods.contains(KeyMods::CTRL);
+        match input.keycode {
+            Some(KeyCode::S) if ctrl => match save_position(&file, &config.piece_config) {
+                Ok(()) => println!("Saved {}", file.display()),
+                Err(error) => eprintln!("could not save {}: {error}", file.display()),
+            },
+            Some(KeyCode::L) if ctrl => match read_position(&file) {
+                Ok(piece_config) => config = BoardConfig::new(piece_config),
+                Err(error) => eprintln!("could not load {}: {error}", file.display()),
+            },
+            Some(KeyCode::E) => editing.set(!editing.get()),
+            Some(KeyCode::W) => config.piece_config.blacks_play = false,
+            Some(KeyCode::B) => config.piece_config.blacks_play = true,
+            Some(KeyCode::D) => config.mesh = HashMap::new(),
+            Some(KeyCode::C) => {
+                config.piece_config.white_pieces = 0;
+                config.piece_config.black_pieces = 0;
+                config.mesh = HashMap::new();
+            }
+            Some(KeyCode::R) => {
+                config.piece_config.white_pieces = START_WHITE;
+                config.piece_config.black_pieces = START_BLACK;
+                config.mesh = HashMap::new();
+            }
+            Some(KeyCode::S) => {
+                let mesh = GameState::from(config.piece_config).move_mask();
+                if mesh != 0 {
+                    config.mesh.insert(mesh, Color::MAGENTA);
                 }
-                config.mesh.insert(mesh, Color::MAGENTA);
             }
+            Some(KeyCode::T) => {
+                let black = config.piece_config.black_pieces;
+                let white = config.piece_config.white_pieces;
+                for (stable, color) in [
+                    (stable_discs(black, white), Color::BLUE),
+                    (stable_discs(white, black), Color::CYAN),
+                ] {
+                    if stable != 0 {
+                        config.mesh.insert(stable, color);
+                    }
+                }
+            }
+            Some(KeyCode::Return) => println!("{}", write_position(&config.piece_config)),
+            _ => (),
         }
-        Some(KeyCode::Return) => println!(
-            "White Pieces: {:?}\tBlack Pieces: {:?}",
-            config.piece_config.white_pieces, config.piece_config.black_pieces
-        ),
-        _ => (),
+        config
     }
-    return config;
 }
 
-fn capture(config: &PieceConfig, position: u64) -> PieceConfig {
-    let mut config = config.clone();
-    if config.blacks_play {
-        config.white_pieces &= !position;
-        config.black_pieces |= position;
-    } else {
-        config.black_pieces &= !position;
-        config.white_pieces |= position;
+// Left places a piece of the side to move, right removes and middle flips
+fn edit(config: &PieceConfig, position: u64, button: MouseButton) -> PieceConfig {
+    let mut config = *config;
+    let (black, white) = (config.black_pieces, config.white_pieces);
+    match button {
+        MouseButton::Left if config.blacks_play => {
+            config.white_pieces &= !position;
+            config.black_pieces |= position;
+        }
+        MouseButton::Left => {
+            config.black_pieces &= !position;
+            config.white_pieces |= position;
+        }
+        MouseButton::Right => {
+            config.black_pieces &= !position;
+            config.white_pieces &= !position;
+        }
+        MouseButton::Middle => {
+            config.black_pieces = (black & !position) | (white & position);
+            config.white_pieces = (white & !position) | (black & position);
+        }
+        MouseButton::Other(_) => {}
     }
     config
 }
+
+// Plays the move, passing for the opponent when it is left without one
+fn capture(config: &PieceConfig, position: u64) -> PieceConfig {
+    let state = GameState::from(*config);
+    let Some(state) = state.apply(Action::Place(position)) else {
+        return *config;
+    };
+    match state.apply(Action::Pass) {
+        Some(passed) => passed.config,
+        None => state.config,
+    }
+}
+
+fn status(config: &PieceConfig, editing: bool) -> Vec<String> {
+    let state = GameState::from(*config);
+    let (black, white) = state.score();
+    let side = if config.blacks_play { "black" } else { "white" };
+    let mut lines = vec![
+        if editing { "editing" } else { "playing" }.to_string(),
+        format!("{side} to move"),
+        format!("black {black} white {white}"),
+    ];
+    if state.is_terminal() {
+        lines.push("game over".to_string());
+    } else if state.must_pass() {
+        lines.push(format!("{side} must pass"));
+    }
+    lines.extend(config.problems());
+    lines
+}
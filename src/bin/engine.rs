@@ -0,0 +1,24 @@
+use std::io::{self, BufRead, Write};
+
+use clap::Parser;
+use reversi::{cli::EngineArgs, engine::Engine, eval::Weights};
+
+fn main() -> io::Result<()> {
+    let args = EngineArgs::parse();
+    let mut engine = Engine::new(args.limits());
+    if let Some(path) = &args.weights {
+        engine = engine.with_weights(Weights::from_file(path)?);
+    }
+
+    let mut output = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        if let Some(response) = engine.respond(&line?) {
+            output.write_all(response.as_bytes())?;
+            output.flush()?;
+        }
+        if engine.is_done() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::{fs, io};
+
+use clap::Parser;
+use reversi::{
+    cli::MatchArgs,
+    game::Side,
+    notation::write_transcript,
+    tournament::{parse_openings, play_game, MatchStats},
+};
+
+fn signed(elo: f64) -> String {
+    if elo.is_infinite() {
+        return if elo > 0.0 { "+inf" } else { "-inf" }.to_string();
+    }
+    format!("{elo:+.0}")
+}
+
+fn main() -> io::Result<()> {
+    let args = MatchArgs::parse();
+    let openings = match &args.openings {
+        Some(path) => parse_openings(&fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    let mut stats = MatchStats::default();
+    for game in 0..args.games {
+        // Every opening is played once with each color
+        let opening = match openings.len() {
+            0 => &[][..],
+            count => &openings[game / 2 % count][..],
+        };
+        let first = if game % 2 == 0 {
+            Side::Black
+        } else {
+            Side::White
+        };
+        let (mut black, mut white) = match first {
+            Side::Black => (args.first.create()?, args.second.create()?),
+            Side::White => (args.second.create()?, args.first.create()?),
+        };
+        let outcome = play_game(&mut *black, &mut *white, opening);
+        stats.add(&outcome, first);
+        let (black, white) = match first {
+            Side::Black => (&args.first, &args.second),
+            Side::White => (&args.second, &args.first),
+        };
+        let forfeit = match outcome.forfeit {
+            Some(side) => format!(", {side:?} forfeits"),
+            None => String::new(),
+        };
+        println!(
+            "game {}: {black} (black) {}-{} {white} (white){forfeit} [{}]",
+            game + 1,
+            outcome.black,
+            outcome.white,
+            write_transcript(opening),
+        );
+    }
+
+    println!("{} against {}", args.first, args.second);
+    println!(
+        "wins/losses/draws: {}/{}/{}",
+        stats.wins, stats.losses, stats.draws
+    );
+    if let (Some(discs), Some((elo, low, high))) = (stats.average_discs(), stats.elo()) {
+        println!("average disc differential: {discs:+.2}");
+        println!(
+            "elo difference: {} (95% confidence: {} to {})",
+            signed(elo),
+            signed(low),
+            signed(high)
+        );
+    }
+    Ok(())
+}
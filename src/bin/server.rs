@@ -1,50 +1,125 @@
 use std::{
-    io::{Read, Write},
-    net::TcpListener,
+    collections::HashMap,
+    io,
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
 };
 
 use clap::Parser;
-use reversi::cli::Args;
+use reversi::{
+    cli::Args,
+    protocol::{read_message, write_message, Message},
+    server::{ClientId, Lobby},
+};
+
+// How often dropped players and clocks are checked for running out of time
+const EXPIRY_INTERVAL: Duration = Duration::from_millis(250);
+// Clients that take longer than this to accept a message are dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Event {
+    Connected(ClientId, TcpStream),
+    Received(ClientId, io::Result<Message>),
+}
+
+// Forwards every message of a connection until it drops
+fn receive(client: ClientId, mut stream: TcpStream, events: Sender<Event>) {
+    loop {
+        let message = read_message(&mut stream);
+        let lost = message.is_err();
+        if events.send(Event::Received(client, message)).is_err() || lost {
+            break;
+        }
+    }
+}
+
+// Writes a connection's messages on a thread of its own, so a client that
+// stops reading only holds up itself. A failed write or the server dropping
+// the client closes the connection and its reader thread reports it
+fn send(mut stream: TcpStream, messages: Receiver<Message>) {
+    for message in messages {
+        if write_message(&mut stream, &message).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+fn writer(stream: TcpStream) -> Sender<Message> {
+    let (sender, messages) = mpsc::channel();
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    thread::spawn(move || send(stream, messages));
+    sender
+}
+
+fn accept(server: TcpListener, events: Sender<Event>) {
+    for (client, stream) in (0..).zip(server.incoming()) {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        println!("client {client} connected from: {:?}", stream.peer_addr());
+        let Ok(reader) = stream.try_clone() else {
+            continue;
+        };
+        // Registered before anything it sends can be answered
+        if events.send(Event::Connected(client, stream)).is_err() {
+            break;
+        }
+        let sender = events.clone();
+        thread::spawn(move || receive(client, reader, sender));
+    }
+}
 
 fn main() {
     let args = Args::parse();
 
     let server = TcpListener::bind(args.addr()).expect("cannot bind on given address");
     println!("listening on: {:?}", args.addr());
-    let (mut stream, addr) = server.accept().expect("cannot accept connection");
-    println!("connected from: {:?}", addr);
-
-    stream
-        .write("pick".as_bytes())
-        .expect("cannot write to connected stream");
-
-    let mut buf = [0; 1024];
-    let read = stream.read(&mut buf).unwrap();
-    let response = String::from_utf8(buf[0..read].to_vec()).unwrap();
-    println!("{addr} plays {response}");
-
-    let (mut challenger, addr) = server.accept().expect("cannot accept connection");
-    challenger.set_read_timeout(None).unwrap();
-    println!("connected from: {:?}", addr);
-    if response == "black".to_string() {
-        stream.write(b"black").unwrap();
-        challenger.write(b"white").unwrap();
-    } else {
-        stream.write(b"white").unwrap();
-        challenger.write(b"black").unwrap();
-    }
 
-    let mut challenger_plays = response == "white".to_string();
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || accept(server, sender));
 
+    // Every game is driven from this loop, which never waits on a socket:
+    // replies go to the writer thread of each client
+    let mut lobby = Lobby::new().with_grace(Duration::from_secs(args.grace));
+    if let Some(time_control) = args.time_control {
+        lobby = lobby.with_time_control(time_control);
+    }
+    let mut streams = HashMap::new();
+    let mut next_expiry = Instant::now();
     loop {
-        let mut buf = [0; 1024];
-        if challenger_plays {
-            challenger.read(&mut buf).unwrap();
-            stream.write(&buf).unwrap();
-        } else {
-            stream.read(&mut buf).unwrap();
-            challenger.write(&buf).unwrap();
+        let mut replies = match events.recv_timeout(EXPIRY_INTERVAL) {
+            Ok(Event::Connected(client, stream)) => {
+                streams.insert(client, writer(stream));
+                Vec::new()
+            }
+            Ok(Event::Received(client, Ok(message))) => {
+                lobby.handle(client, message, Instant::now())
+            }
+            Ok(Event::Received(client, Err(error))) => {
+                println!("client {client} disconnected: {error}");
+                streams.remove(&client);
+                lobby.disconnect(client, Instant::now())
+            }
+            Err(RecvTimeoutError::Timeout) => Vec::new(),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let now = Instant::now();
+        if now >= next_expiry {
+            replies.extend(lobby.expire(now));
+            next_expiry = now + EXPIRY_INTERVAL;
+        }
+        for (client, reply) in replies {
+            if let Some(stream) = streams.get(&client) {
+                // A dropped connection shows up on its reader thread
+                let _ = stream.send(reply);
+            }
+        }
+        // Writers finish what was sent to them before closing
+        for client in lobby.replaced() {
+            streams.remove(&client);
         }
-        challenger_plays = !challenger_plays;
     }
 }
@@ -0,0 +1,147 @@
+use std::{io, path::Path, process, time::Instant};
+
+use clap::Parser;
+use reversi::{
+    book::Book,
+    cli::{DatabaseFormat, StudyArgs, StudyCommand},
+    database::{GameRecord, GgfReader, Summary, WthorReader},
+    game::GameState,
+    notation::{
+        action_name, parse_transcript, parse_transcript_from, read_position, replay, square_name,
+    },
+    perft::{divide, perft},
+};
+
+fn summarize(games: impl Iterator<Item = io::Result<GameRecord>>, plies: usize) -> Summary {
+    let mut summary = Summary::default();
+    for game in games {
+        match game {
+            Ok(game) => summary.add(&game, plies),
+            Err(error) => {
+                eprintln!(
+                    "skipping game {}: {error}",
+                    summary.games + summary.errors + 1
+                );
+                summary.add_error();
+            }
+        }
+    }
+    summary
+}
+
+fn stats(path: &Path, format: DatabaseFormat, openings: usize, plies: usize) -> io::Result<()> {
+    let summary = match format {
+        DatabaseFormat::Ggf => summarize(GgfReader::open(path)?, plies),
+        DatabaseFormat::Wthor => summarize(WthorReader::open(path)?, plies),
+    };
+    println!("games: {}", summary.games);
+    println!("errors: {}", summary.errors);
+    match summary.average_result() {
+        Some(average) => println!("average score (black - white): {average:+.2}"),
+        None => println!("average score (black - white): -"),
+    }
+    println!("top openings ({plies} plies):");
+    for (opening, games) in summary.top_openings(openings) {
+        let share = 100.0 * games as f64 / summary.games as f64;
+        println!("  {opening:<16} {games:>8} {share:>6.2}%");
+    }
+    Ok(())
+}
+
+fn book(path: &Path, format: Option<DatabaseFormat>, plies: usize, moves: &str) -> io::Result<()> {
+    let moves = parse_transcript(moves)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let book = match format {
+        None => Book::from_file(path, plies)?,
+        Some(format) => {
+            let mut book = Book::new();
+            let games: Box<dyn Iterator<Item = io::Result<GameRecord>>> = match format {
+                DatabaseFormat::Ggf => Box::new(GgfReader::open(path)?),
+                DatabaseFormat::Wthor => Box::new(WthorReader::open(path)?),
+            };
+            for game in games {
+                match game {
+                    Ok(game) => book.add_record(&game, plies),
+                    Err(error) => eprintln!("skipping game: {error}"),
+                }
+            }
+            book
+        }
+    };
+    let positions = replay(&GameState::new(), &moves).unwrap();
+    let configs: Vec<_> = positions.iter().map(|state| state.config).collect();
+    println!("opening: {}", book.opening(&configs).unwrap_or("-"));
+    for book_move in book.moves(configs.last().unwrap()) {
+        match book_move.score {
+            Some(score) => println!(
+                "{} {} {score:+.2}",
+                square_name(book_move.position),
+                book_move.games
+            ),
+            None => println!("{} {} -", square_name(book_move.position), book_move.games),
+        }
+    }
+    Ok(())
+}
+
+fn run_perft(depth: u32, position: Option<&Path>, moves: &str, split: bool) -> io::Result<()> {
+    let start = match position {
+        Some(path) => GameState::from(read_position(path)?),
+        None => GameState::new(),
+    };
+    let moves = parse_transcript_from(&start, moves)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let state = *replay(&start, &moves).unwrap().last().unwrap();
+    let started = Instant::now();
+    let nodes = if split {
+        let mut nodes = 0;
+        for (action, count) in divide(&state, depth) {
+            println!("{} {count}", action_name(action));
+            nodes += count;
+        }
+        nodes
+    } else {
+        perft(&state, depth)
+    };
+    let elapsed = started.elapsed();
+    println!("nodes: {nodes}");
+    println!(
+        "time: {:.3}s ({:.0} nodes/s)",
+        elapsed.as_secs_f64(),
+        nodes as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+fn main() {
+    let args = StudyArgs::parse();
+    let result = match args.command {
+        StudyCommand::Stats {
+            path,
+            format,
+            openings,
+            plies,
+        } => stats(
+            &path,
+            format.unwrap_or_else(|| DatabaseFormat::from_path(&path)),
+            openings,
+            plies,
+        ),
+        StudyCommand::Book {
+            path,
+            format,
+            plies,
+            moves,
+        } => book(&path, format, plies, &moves),
+        StudyCommand::Perft {
+            depth,
+            position,
+            moves,
+            divide,
+        } => run_perft(depth, position.as_deref(), &moves, divide),
+    };
+    if let Err(error) = result {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
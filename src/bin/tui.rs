@@ -0,0 +1,498 @@
+use std::{
+    cell::Cell,
+    io::{self, Stdout, Write},
+    process,
+    rc::Rc,
+    time::Duration,
+};
+
+use clap::Parser;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{self, Color, Stylize},
+    terminal::{self, ClearType},
+};
+use reversi::{
+    bitboard_position,
+    book::Book,
+    cli::{TuiArgs, TuiMode},
+    client::{ClockView, Link},
+    controller::{self, Controller, Input, Move, Player},
+    engine::side_name,
+    game::{Action, GameState, PieceConfig, Side},
+    notation::{parse_square, replay, square_name},
+    players::AiPlayer,
+    protocol::Message,
+    search::SearchLimits,
+};
+
+// How often the screen is redrawn while nothing happens, for the clocks
+const TICK: Duration = Duration::from_millis(100);
+// Column the text next to the board starts at
+const PANEL: u16 = 22;
+
+// Who makes the moves of a side
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Seat {
+    Human,
+    Ai,
+    // The opponent on the server
+    Remote,
+}
+
+// Leaves its moves to the keys, which select squares on the board
+struct Keys;
+
+impl Player for Keys {
+    fn play_move(&mut self, _config: &PieceConfig) -> Move {
+        Move::Board
+    }
+
+    fn enemy_move(&mut self, _current_move: u64) {}
+}
+
+// Plays the move the server sent for the opponent, if any
+struct Remote(Rc<Cell<Option<u64>>>);
+
+impl Player for Remote {
+    fn play_move(&mut self, _config: &PieceConfig) -> Move {
+        match self.0.take() {
+            Some(position) => Move::Position(position),
+            None => Move::Pending,
+        }
+    }
+
+    fn enemy_move(&mut self, _current_move: u64) {}
+}
+
+fn capture(config: &PieceConfig, position: u64) -> PieceConfig {
+    let state = GameState::from(*config)
+        .apply(Action::Place(position))
+        .unwrap();
+    match state.must_pass() {
+        true => state.apply(Action::Pass).unwrap().config,
+        false => state.config,
+    }
+}
+
+fn valid(config: &PieceConfig, position: u64) -> bool {
+    GameState::from(*config).move_mask() & position != 0
+}
+
+struct App {
+    controller: Controller,
+    // Every action of the game including passes
+    moves: Vec<Action>,
+    black: Seat,
+    white: Seat,
+    // Move of the opponent on the server waiting to be played
+    remote: Rc<Cell<Option<u64>>>,
+    link: Option<Link>,
+    // None for untimed games
+    clock: Option<ClockView>,
+    book: Book,
+    // Square the arrow keys move around, row and column from a1
+    cursor: (u8, u8),
+    // Coordinates typed so far
+    typed: String,
+    message: String,
+    // Set once the game ended other than on the board, by resignation, time
+    // or a lost connection
+    over: bool,
+    quit: bool,
+}
+
+impl App {
+    fn new(black: Seat, white: Seat, depth: u8) -> App {
+        let remote = Rc::new(Cell::new(None));
+        let player = |seat: Seat| -> Box<dyn Player> {
+            match seat {
+                Seat::Human => Box::new(Keys),
+                Seat::Ai => Box::new(AiPlayer::new(SearchLimits::depth(depth))),
+                Seat::Remote => Box::new(Remote(remote.clone())),
+            }
+        };
+        let controller = Controller::new(
+            GameState::new().config,
+            Box::new(capture),
+            Box::new(valid),
+            player(black),
+            player(white),
+        );
+        App {
+            controller,
+            moves: Vec::new(),
+            black,
+            white,
+            remote,
+            link: None,
+            clock: None,
+            book: Book::new(),
+            cursor: (4, 5),
+            typed: String::new(),
+            message: String::new(),
+            over: false,
+            quit: false,
+        }
+    }
+
+    fn state(&self) -> GameState {
+        GameState::from(*self.controller.config())
+    }
+
+    fn seat(&self, side: Side) -> Seat {
+        match side {
+            Side::Black => self.black,
+            Side::White => self.white,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.over || self.state().is_terminal()
+    }
+
+    // Human to move, the only time keys place discs
+    fn humans_turn(&self) -> bool {
+        !self.finished() && self.seat(self.state().side_to_move()) == Seat::Human
+    }
+
+    fn end(&mut self, reason: String) {
+        self.message = reason;
+        self.over = true;
+    }
+
+    // Steps the game and shows what happened, our moves go to the server
+    fn step(&mut self, input: Input) {
+        for event in self.controller.step(input) {
+            match event {
+                controller::Event::Played(side, position) => {
+                    self.moves.push(Action::Place(position));
+                    self.message.clear();
+                    if self.seat(side) == Seat::Human && self.link.is_some() {
+                        self.send_move(position);
+                    }
+                }
+                controller::Event::Passed(side) => {
+                    self.moves.push(Action::Pass);
+                    self.message = format!("{} passes", side_name(side));
+                }
+                controller::Event::GameOver { black, white } => {
+                    self.message = match self.state().winner() {
+                        Some(side) => {
+                            format!("game over {black}-{white}, {} wins", side_name(side))
+                        }
+                        None => format!("game over {black}-{white}, draw"),
+                    };
+                }
+                controller::Event::Rejected(position) => {
+                    self.message = format!("{} is not legal", square_name(position));
+                }
+                controller::Event::Reset | controller::Event::Jumped(_) => {}
+            }
+        }
+    }
+
+    fn send_move(&mut self, position: u64) {
+        let Some(link) = &mut self.link else {
+            return;
+        };
+        if link.send(&Message::Move(position)).is_err() {
+            self.resume();
+        }
+    }
+
+    // Gets back into the game after losing the connection, resending moves
+    // of ours the server never got or taking the server's game if it differs
+    fn resume(&mut self) {
+        let Some(link) = &mut self.link else {
+            return;
+        };
+        let moves = match link.reconnect() {
+            Ok(moves) => moves,
+            Err(error) => return self.end(format!("cannot resume game: {error}")),
+        };
+        if self.moves.starts_with(&moves) {
+            for &action in &self.moves[moves.len()..] {
+                if let Action::Place(position) = action {
+                    if link.send(&Message::Move(position)).is_err() {
+                        return self.end("connection lost".to_string());
+                    }
+                }
+            }
+            return;
+        }
+        match replay(&GameState::new(), &moves) {
+            Some(positions) => {
+                self.step(Input::SetPosition(positions.last().unwrap().config));
+                self.moves = moves;
+            }
+            None => self.end("server sent an illegal game".to_string()),
+        }
+    }
+
+    // Handles what the server sent since the last tick
+    fn poll(&mut self) {
+        loop {
+            let Some(message) = self.link.as_mut().and_then(|link| link.try_read()) else {
+                return;
+            };
+            match message {
+                Ok(Message::Move(position)) => {
+                    let state = self.state();
+                    let remote = self.seat(state.side_to_move()) == Seat::Remote;
+                    if !remote || state.move_mask() & position == 0 {
+                        self.end("opponent sent an illegal move".to_string());
+                        continue;
+                    }
+                    self.remote.set(Some(position));
+                    self.step(Input::Poll);
+                }
+                Ok(Message::Resign) => self.end("opponent resigned".to_string()),
+                Ok(Message::TimeOut(side)) => self.end(format!("{} lost on time", side_name(side))),
+                Ok(Message::Error(error)) => self.end(format!("server error: {error}")),
+                Ok(Message::Clock {
+                    black,
+                    white,
+                    period,
+                }) => self.clock = Some(ClockView::new(black, white, period)),
+                Ok(Message::Paused { seconds }) => {
+                    self.message = format!("opponent disconnected, waiting {seconds}s");
+                    let side = self.state().side_to_move();
+                    if let Some(clock) = &mut self.clock {
+                        clock.pause(side);
+                    }
+                }
+                Ok(Message::Unpaused) => self.message = "opponent reconnected".to_string(),
+                // Passes are played locally and pings need no answer
+                Ok(_) => {}
+                Err(_) if self.finished() => return,
+                Err(_) => self.resume(),
+            }
+        }
+    }
+
+    // Lets the AI move when it is its turn
+    fn think(&mut self) {
+        if self.finished() || self.seat(self.state().side_to_move()) != Seat::Ai {
+            return;
+        }
+        self.step(Input::Poll);
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            return;
+        }
+        let (row, column) = self.cursor;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Up => self.cursor = (row.saturating_sub(1), column),
+            KeyCode::Down => self.cursor = ((row + 1).min(7), column),
+            KeyCode::Left => self.cursor = (row, column.saturating_sub(1)),
+            KeyCode::Right => self.cursor = (row, (column + 1).min(7)),
+            KeyCode::Backspace => {
+                self.typed.pop();
+            }
+            KeyCode::Char(c @ ('a'..='h' | 'A'..='H')) => {
+                self.typed = c.to_ascii_lowercase().to_string();
+            }
+            KeyCode::Char(c @ '1'..='8') if self.typed.len() == 1 => {
+                self.typed.push(c);
+                // A full square moves the cursor there, enter plays it
+                let position = parse_square(&self.typed).unwrap();
+                self.cursor = reversi::bitboard_rowcol(position);
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                self.typed.clear();
+                if !self.humans_turn() {
+                    return;
+                }
+                self.step(Input::Select(bitboard_position(row, column)));
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&self, out: &mut Stdout) -> io::Result<()> {
+        let moves = if self.humans_turn() {
+            self.state().move_mask()
+        } else {
+            0
+        };
+        let config = self.state().config;
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            style::Print("  a b c d e f g h ")
+        )?;
+        for row in 0..8 {
+            queue!(
+                out,
+                cursor::MoveTo(0, row as u16 + 1),
+                style::Print(format!("{} ", row + 1))
+            )?;
+            for column in 0..8 {
+                let position = bitboard_position(row, column);
+                let disc = if config.black_pieces & position != 0 {
+                    "●".with(Color::Black)
+                } else if config.white_pieces & position != 0 {
+                    "●".with(Color::White)
+                } else if moves & position != 0 {
+                    "·".with(Color::Yellow)
+                } else {
+                    " ".stylize()
+                };
+                let background = if (row, column) == self.cursor {
+                    Color::DarkYellow
+                } else {
+                    Color::DarkGreen
+                };
+                queue!(
+                    out,
+                    style::PrintStyledContent(disc.on(background)),
+                    style::PrintStyledContent(" ".on(background))
+                )?;
+            }
+        }
+        for (line, text) in self.panel().iter().enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(PANEL, line as u16),
+                terminal::Clear(ClearType::UntilNewLine),
+                style::Print(text)
+            )?;
+        }
+        out.flush()
+    }
+
+    // Lines of text next to the board
+    fn panel(&self) -> Vec<String> {
+        let (black, white) = self.state().score();
+        let side = self.state().side_to_move();
+        let mut lines = vec![
+            format!("black {black}  white {white}"),
+            if self.finished() {
+                "game over".to_string()
+            } else {
+                format!("{} to move", side_name(side))
+            },
+        ];
+        if let Some(clock) = &self.clock {
+            let running = (!self.finished()).then_some(side);
+            for side in [Side::Black, Side::White] {
+                lines.push(format!(
+                    "{} {}",
+                    side_name(side),
+                    clock.remaining(side, running)
+                ));
+            }
+        }
+        let positions = replay(&GameState::new(), &self.moves).unwrap_or_default();
+        let configs: Vec<_> = positions.iter().map(|state| state.config).collect();
+        lines.push(
+            self.book
+                .opening(&configs)
+                .map(|name| format!("opening: {name}"))
+                .unwrap_or_default(),
+        );
+        lines.push(String::new());
+        lines.push(self.message.clone());
+        lines.push(format!("move: {}", self.typed));
+        lines.push(String::new());
+        lines.push("arrows or type a square, enter".to_string());
+        lines.push("to play, q to quit".to_string());
+        lines
+    }
+}
+
+// Puts the terminal back however the program ends
+struct Screen(Stdout);
+
+impl Screen {
+    fn open() -> io::Result<Screen> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(ClearType::All)
+        )?;
+        Ok(Screen(out))
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.0, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Connects and waits for the game to start, still on the normal screen
+fn join_server(args: &TuiArgs, side: Side) -> io::Result<(Link, Side)> {
+    let name = concat!("reversi tui ", env!("CARGO_PKG_VERSION"));
+    let mut link = Link::connect(args.addr(), name)?;
+    println!("connected to {}", link.server);
+    let request = match args.join {
+        Some(id) => Message::JoinGame(id),
+        None => Message::CreateGame(side),
+    };
+    link.send(&request)?;
+    let side = link.wait_for_game(|id| println!("created game {id}, waiting for an opponent"))?;
+    Ok((link, side))
+}
+
+fn run(mut app: App) -> io::Result<()> {
+    let mut screen = Screen::open()?;
+    while !app.quit {
+        app.poll();
+        app.draw(&mut screen.0)?;
+        app.think();
+        if event::poll(TICK)? {
+            match event::read()? {
+                Event::Key(key) => app.key(key),
+                Event::Resize(_, _) => {
+                    execute!(screen.0, terminal::Clear(ClearType::All))?;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = TuiArgs::parse();
+    let side = if args.white_piece {
+        Side::White
+    } else {
+        Side::Black
+    };
+    let app = match args.mode {
+        TuiMode::Local => App::new(Seat::Human, Seat::Human, args.depth),
+        TuiMode::Ai => match side {
+            Side::Black => App::new(Seat::Human, Seat::Ai, args.depth),
+            Side::White => App::new(Seat::Ai, Seat::Human, args.depth),
+        },
+        TuiMode::Network => {
+            let (link, side) = join_server(&args, side).unwrap_or_else(|error| {
+                eprintln!("cannot start a network game: {error}");
+                process::exit(1);
+            });
+            let mut app = match side {
+                Side::Black => App::new(Seat::Human, Seat::Remote, args.depth),
+                Side::White => App::new(Seat::Remote, Seat::Human, args.depth),
+            };
+            app.link = Some(link);
+            app
+        }
+    };
+    if let Err(error) = run(app) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
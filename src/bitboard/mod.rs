@@ -0,0 +1,366 @@
+use std::{
+    fmt,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Sub},
+    str::FromStr,
+};
+
+use crate::notation::{parse_square, square_name};
+
+// One square of the board numbered 0 for a1 to 63 for h8, row by row with a1
+// in the top left corner like everywhere else in the crate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Square(u8);
+
+impl Square {
+    // Rank and file from 0, rank 0 is the top row holding a1
+    pub fn new(rank: u8, file: u8) -> Option<Square> {
+        (rank < 8 && file < 8).then_some(Square(rank * 8 + file))
+    }
+
+    pub fn from_index(index: u8) -> Option<Square> {
+        (index < 64).then_some(Square(index))
+    }
+
+    // Square of a bitboard holding exactly one piece
+    pub fn from_bit(position: u64) -> Option<Square> {
+        position
+            .is_power_of_two()
+            .then(|| Square(position.leading_zeros() as u8))
+    }
+
+    pub fn index(self) -> u8 {
+        self.0
+    }
+
+    pub fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    // Single bit of the square in the crate's u64 bitboards
+    pub fn bit(self) -> u64 {
+        1 << (63 - self.0)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", square_name(self.bit()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseSquareError(String);
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not a square", self.0)
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+// Parses coordinates such as "d3" or "D3"
+impl FromStr for Square {
+    type Err = ParseSquareError;
+
+    fn from_str(text: &str) -> Result<Square, ParseSquareError> {
+        parse_square(text)
+            .and_then(Square::from_bit)
+            .ok_or_else(|| ParseSquareError(text.to_string()))
+    }
+}
+
+// Set of squares, a thin wrapper around the u64 bitboards the rest of the
+// crate passes around
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FULL: Bitboard = Bitboard(u64::MAX);
+
+    pub fn contains(self, square: Square) -> bool {
+        self.0 & square.bit() != 0
+    }
+
+    pub fn insert(&mut self, square: Square) {
+        self.0 |= square.bit();
+    }
+
+    pub fn remove(&mut self, square: Square) {
+        self.0 &= !square.bit();
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    // First square from a1 onwards
+    pub fn first(self) -> Option<Square> {
+        (self.0 != 0).then(|| Square(self.0.leading_zeros() as u8))
+    }
+
+    // Squares from a1 to h8 without allocating
+    pub fn iter(self) -> Squares {
+        Squares(self.0)
+    }
+
+    // Every empty square where ally, this set, encapsulates foe pieces
+    pub fn legal_moves(self, foe: Bitboard) -> Bitboard {
+        Bitboard(crate::legal_moves(self.0, foe.0))
+    }
+
+    // Foe pieces flipped by ally placing a piece on the square
+    pub fn flips(self, foe: Bitboard, square: Square) -> Bitboard {
+        Bitboard(crate::flips(self.0, foe.0, square.bit()))
+    }
+
+    // Ally pieces that can never be flipped
+    pub fn stable(self, foe: Bitboard) -> Bitboard {
+        Bitboard(crate::stable_discs(self.0, foe.0))
+    }
+
+    // Squares next to any square of the set in all 8 directions
+    pub fn neighbours(self) -> Bitboard {
+        Bitboard(crate::neighbours(self.0))
+    }
+}
+
+// Bit scan over a bitboard, clearing one bit for every square
+#[derive(Clone, Copy, Debug)]
+pub struct Squares(u64);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.leading_zeros();
+        self.0 &= !(1 << (63 - index));
+        Some(Square(index as u8))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+
+// From h8 backwards
+impl DoubleEndedIterator for Squares {
+    fn next_back(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = 63 - self.0.trailing_zeros();
+        self.0 &= self.0 - 1;
+        Some(Square(index as u8))
+    }
+}
+
+impl ExactSizeIterator for Squares {}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = Squares;
+
+    fn into_iter(self) -> Squares {
+        self.iter()
+    }
+}
+
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<I: IntoIterator<Item = Square>>(squares: I) -> Bitboard {
+        squares
+            .into_iter()
+            .fold(Bitboard::EMPTY, |board, square| board | square)
+    }
+}
+
+impl From<u64> for Bitboard {
+    fn from(board: u64) -> Bitboard {
+        Bitboard(board)
+    }
+}
+
+impl From<Bitboard> for u64 {
+    fn from(board: Bitboard) -> u64 {
+        board.0
+    }
+}
+
+impl From<Square> for Bitboard {
+    fn from(square: Square) -> Bitboard {
+        Bitboard(square.bit())
+    }
+}
+
+impl<T: Into<Bitboard>> BitAnd<T> for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, other: T) -> Bitboard {
+        Bitboard(self.0 & other.into().0)
+    }
+}
+
+impl<T: Into<Bitboard>> BitOr<T> for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, other: T) -> Bitboard {
+        Bitboard(self.0 | other.into().0)
+    }
+}
+
+impl<T: Into<Bitboard>> BitXor<T> for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, other: T) -> Bitboard {
+        Bitboard(self.0 ^ other.into().0)
+    }
+}
+
+// Squares of the first set missing from the second
+impl<T: Into<Bitboard>> Sub<T> for Bitboard {
+    type Output = Bitboard;
+
+    fn sub(self, other: T) -> Bitboard {
+        Bitboard(self.0 & !other.into().0)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+impl<T: Into<Bitboard>> BitAndAssign<T> for Bitboard {
+    fn bitand_assign(&mut self, other: T) {
+        self.0 &= other.into().0;
+    }
+}
+
+impl<T: Into<Bitboard>> BitOrAssign<T> for Bitboard {
+    fn bitor_assign(&mut self, other: T) {
+        self.0 |= other.into().0;
+    }
+}
+
+impl<T: Into<Bitboard>> BitXorAssign<T> for Bitboard {
+    fn bitxor_assign(&mut self, other: T) {
+        self.0 ^= other.into().0;
+    }
+}
+
+// Grid with X on the squares of the set, laid out like board diagrams
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "  a b c d e f g h")?;
+        for rank in 0..8 {
+            write!(f, "\n{}", rank + 1)?;
+            for file in 0..8 {
+                let square = Square(rank * 8 + file);
+                write!(f, " {}", if self.contains(square) { 'X' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard_position;
+    use crate::game::{START_BLACK, START_WHITE};
+
+    #[test]
+    fn test_square() {
+        let d3: Square = "d3".parse().unwrap();
+        assert_eq!((d3.rank(), d3.file()), (2, 3));
+        assert_eq!(d3.bit(), bitboard_position(2, 3));
+        assert_eq!(Square::from_bit(d3.bit()), Some(d3));
+        assert_eq!(Square::new(2, 3), Some(d3));
+        assert_eq!(d3.to_string(), "d3");
+        assert_eq!(Square::from_index(0).unwrap().to_string(), "a1");
+        assert_eq!(Square::from_index(63).unwrap().to_string(), "h8");
+        assert_eq!(Square::from_index(64), None);
+        assert_eq!(Square::new(8, 0), None);
+        assert_eq!(Square::from_bit(0b11), None);
+        assert!("i9".parse::<Square>().is_err());
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let black = Bitboard(START_BLACK);
+        let white = Bitboard(START_WHITE);
+        let d5: Square = "d5".parse().unwrap();
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!(black.count(), 2);
+        assert!(black.contains(d5) && !white.contains(d5));
+        assert_eq!((black | white).count(), 4);
+        assert!((black & white).is_empty());
+        assert_eq!(black ^ d5, Bitboard::from(e4));
+        assert_eq!((black | white) - white, black);
+        assert_eq!(!Bitboard::EMPTY, Bitboard::FULL);
+        let mut board = Bitboard::EMPTY;
+        board.insert(d5);
+        board |= e4;
+        assert_eq!(board, black);
+        board.remove(d5);
+        assert_eq!(board.first(), Some(e4));
+    }
+
+    #[test]
+    fn test_iteration() {
+        let board = Bitboard(START_BLACK | START_WHITE);
+        let names: Vec<String> = board.iter().map(|square| square.to_string()).collect();
+        assert_eq!(names, ["d4", "e4", "d5", "e5"]);
+        assert_eq!(board.iter().len(), 4);
+        assert_eq!(board.iter().next_back(), "e5".parse().ok());
+        assert_eq!(board.into_iter().collect::<Bitboard>(), board);
+        assert_eq!(Bitboard::FULL.iter().count(), 64);
+        assert_eq!(Bitboard::EMPTY.iter().next(), None);
+    }
+
+    #[test]
+    fn test_moves() {
+        let black = Bitboard(START_BLACK);
+        let white = Bitboard(START_WHITE);
+        let moves: Vec<String> = black
+            .legal_moves(white)
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(moves, ["d3", "c4", "f5", "e6"]);
+        let f5: Square = "f5".parse().unwrap();
+        assert_eq!(
+            black.flips(white, f5),
+            Bitboard::from("e5".parse::<Square>().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let lines: Vec<String> = Bitboard(START_BLACK)
+            .to_string()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines[0], "  a b c d e f g h");
+        assert_eq!(lines[4], "4 . . . . X . . .");
+        assert_eq!(lines[5], "5 . . . X . . . .");
+        assert_eq!(lines.len(), 9);
+    }
+}
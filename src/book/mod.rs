@@ -0,0 +1,299 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::database::GameRecord;
+use crate::game::{Action, GameState, PieceConfig};
+use crate::notation::parse_transcript;
+use crate::symmetry::{canonical, Symmetry};
+use crate::transposition::hash;
+
+// Plies of every game that go into a book unless told otherwise
+pub const DEFAULT_PLIES: usize = 20;
+
+// Well known openings by the moves leading to them, every position reached
+// by turning or mirroring the board has the same name
+const OPENINGS: &[(&str, &str)] = &[
+    ("Perpendicular", "f5d6"),
+    ("Diagonal", "f5f6"),
+    ("Parallel", "f5f4"),
+    ("Tiger", "f5d6c3d3c4"),
+    ("Leader's Tiger", "f5d6c3d3c4f4f6"),
+    ("Cow", "f5d6c5"),
+    ("Rose", "f5d6c5f4e3c6d3f6e6d7"),
+    ("Buffalo", "f5f6e6f4c3"),
+    ("Heath", "f5f6e6f4g5"),
+];
+
+// A move of the book in the position it was looked up for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BookMove {
+    pub position: u64,
+    // Games the move was played in
+    pub games: u32,
+    // Average final disc difference for the side playing it, None if no
+    // game with the move was played out
+    pub score: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct MoveStats {
+    games: u32,
+    // Games played out and the sum of their disc differences for the mover
+    finished: u32,
+    total: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Entry {
+    name: Option<String>,
+    // Moves on the board the position is normalized to
+    moves: HashMap<u64, MoveStats>,
+}
+
+// Moves played in a collection of games and opening names by position, each
+// position is stored once for all 8 ways to turn or mirror the board
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Book {
+    entries: HashMap<u64, Entry>,
+}
+
+impl Default for Book {
+    fn default() -> Self {
+        Book::new()
+    }
+}
+
+// Key of the canonical form of the position, so every symmetric position is
+// found under the same key, and the symmetries leading to it
+fn normalize(config: &PieceConfig) -> (u64, Vec<Symmetry>) {
+    let (form, symmetry) = canonical(config);
+    let mut symmetries = vec![symmetry];
+    symmetries.extend(
+        Symmetry::ALL
+            .into_iter()
+            .filter(|&other| other != symmetry && other.apply_config(config) == form),
+    );
+    (hash(&form), symmetries)
+}
+
+// Square in the normalized position, moves that are the same in a symmetric
+// position end up on the same square
+fn normalize_move(symmetries: &[Symmetry], position: u64) -> u64 {
+    symmetries
+        .iter()
+        .map(|symmetry| symmetry.apply(position))
+        .min()
+        .unwrap()
+}
+
+impl Book {
+    // Book with the names of well known openings and no moves yet
+    pub fn new() -> Book {
+        let mut book = Book {
+            entries: HashMap::new(),
+        };
+        for (name, transcript) in OPENINGS {
+            let moves = parse_transcript(transcript).expect("opening is not legal");
+            book.add_name(name, &moves);
+        }
+        book
+    }
+
+    // Names the position reached by the moves from the start
+    pub fn add_name(&mut self, name: &str, moves: &[Action]) {
+        let mut state = GameState::new();
+        for &action in moves {
+            state = state.apply(action).expect("named opening is not legal");
+        }
+        let (key, _) = normalize(&state.config);
+        self.entries.entry(key).or_default().name = Some(name.to_string());
+    }
+
+    // Adds the first plies moves of a game played from the start, result is
+    // the final disc difference for black if the game was played out
+    pub fn add_game(&mut self, moves: &[Action], result: Option<i32>, plies: usize) {
+        let mut state = GameState::new();
+        for &action in moves.iter().take(plies) {
+            if let Action::Place(position) = action {
+                let (key, symmetries) = normalize(&state.config);
+                let stats = self
+                    .entries
+                    .entry(key)
+                    .or_default()
+                    .moves
+                    .entry(normalize_move(&symmetries, position))
+                    .or_default();
+                stats.games += 1;
+                if let Some(result) = result {
+                    stats.finished += 1;
+                    stats.total += if state.config.blacks_play {
+                        result as i64
+                    } else {
+                        -result as i64
+                    };
+                }
+            }
+            match state.apply(action) {
+                Some(next) => state = next,
+                None => return,
+            }
+        }
+    }
+
+    pub fn add_record(&mut self, game: &GameRecord, plies: usize) {
+        if game.start == GameState::new() {
+            self.add_game(&game.moves, game.result, plies);
+        }
+    }
+
+    // Book from one transcript per line, blank lines and lines starting with
+    // # are skipped. Games that end count with their final disc difference
+    pub fn from_transcripts(text: &str, plies: usize) -> io::Result<Book> {
+        let mut book = Book::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let moves = parse_transcript(line).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("game on line {}: {error}", number + 1),
+                )
+            })?;
+            let mut state = GameState::new();
+            for &action in &moves {
+                state = state.apply(action).unwrap();
+            }
+            let result = state.is_terminal().then(|| {
+                let (black, white) = state.score();
+                black as i32 - white as i32
+            });
+            book.add_game(&moves, result, plies);
+        }
+        Ok(book)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>, plies: usize) -> io::Result<Book> {
+        Book::from_transcripts(&fs::read_to_string(path)?, plies)
+    }
+
+    // Name of the opening if the position has one
+    pub fn name(&self, config: &PieceConfig) -> Option<&str> {
+        let (key, _) = normalize(config);
+        self.entries.get(&key)?.name.as_deref()
+    }
+
+    // Name of the last named position of a game, the opening it was played
+    // with even after leaving the known moves
+    pub fn opening<'a>(
+        &self,
+        positions: impl IntoIterator<Item = &'a PieceConfig>,
+    ) -> Option<&str> {
+        positions
+            .into_iter()
+            .filter_map(|config| self.name(config))
+            .last()
+    }
+
+    // Moves played in the position, most played first
+    pub fn moves(&self, config: &PieceConfig) -> Vec<BookMove> {
+        let (key, symmetries) = normalize(config);
+        let symmetry = symmetries[0];
+        let Some(entry) = self.entries.get(&key) else {
+            return Vec::new();
+        };
+        let mut moves: Vec<BookMove> = entry
+            .moves
+            .iter()
+            .map(|(&position, stats)| BookMove {
+                position: symmetry.inverse().apply(position),
+                games: stats.games,
+                score: (stats.finished > 0).then(|| stats.total as f64 / stats.finished as f64),
+            })
+            .collect();
+        moves.sort_by(|a, b| b.games.cmp(&a.games).then(a.position.cmp(&b.position)));
+        moves
+    }
+
+    // Move with the best average result among the ones played out, the most
+    // played one if no game was
+    pub fn best_move(&self, config: &PieceConfig) -> Option<u64> {
+        let moves = self.moves(config);
+        let scored = moves
+            .iter()
+            .filter_map(|book_move| Some((book_move.score?, book_move)))
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+        match scored {
+            Some((_, book_move)) => Some(book_move.position),
+            None => moves.first().map(|book_move| book_move.position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{parse_square, replay};
+
+    fn position(transcript: &str) -> PieceConfig {
+        let moves = parse_transcript(transcript).unwrap();
+        replay(&GameState::new(), &moves)
+            .unwrap()
+            .last()
+            .unwrap()
+            .config
+    }
+
+    #[test]
+    fn test_names() {
+        let book = Book::new();
+        assert_eq!(book.name(&position("f5d6c3d3c4")), Some("Tiger"));
+        // The same opening started on another square
+        assert_eq!(book.name(&position("e6f4c3c4d3")), Some("Tiger"));
+        assert_eq!(book.name(&position("c4c3")), Some("Diagonal"));
+        assert_eq!(book.name(&position("f5d6c3d3c4f4c5")), None);
+        let moves = parse_transcript("f5d6c3d3c4f4c5b3").unwrap();
+        let positions: Vec<PieceConfig> = replay(&GameState::new(), &moves)
+            .unwrap()
+            .iter()
+            .map(|state| state.config)
+            .collect();
+        assert_eq!(book.opening(&positions), Some("Tiger"));
+        assert_eq!(book.opening(&positions[..2]), None);
+    }
+
+    #[test]
+    fn test_moves() {
+        let book = Book::from_transcripts(
+            "# four games\nf5d6c3d3c4\nf5d6c5f4e3f6g5e6e7\nf5f6\nd3\n",
+            4,
+        )
+        .unwrap();
+        let start = GameState::new().config;
+        let moves = book.moves(&start);
+        // The games opened on squares that are the same up to symmetry
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].games, 4);
+        assert_eq!(moves[0].score, Some(13.0));
+
+        let after = position("f5");
+        let d6 = parse_square("d6").unwrap();
+        assert_eq!(book.best_move(&after), Some(d6));
+        assert_eq!(
+            book.moves(&after)
+                .iter()
+                .map(|book_move| (book_move.position, book_move.games))
+                .collect::<Vec<_>>(),
+            [(d6, 2), (parse_square("f6").unwrap(), 1)]
+        );
+        // Looked up in a turned board the moves are turned too
+        let turned = position("d3");
+        assert_eq!(book.best_move(&turned), parse_square("c5"));
+        assert_eq!(book.moves(&position("f5d6c3d3c4")), []);
+    }
+
+    #[test]
+    fn test_invalid_transcript() {
+        assert!(Book::from_transcripts("f5f5", 10).is_err());
+    }
+}
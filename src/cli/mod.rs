@@ -1,4 +1,12 @@
-use clap::Parser;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::book::DEFAULT_PLIES;
+use crate::clock::TimeControl;
+use crate::search::SearchLimits;
+use crate::tournament::Contestant;
 
 #[derive(Debug, Parser)]
 pub struct Args {
@@ -11,6 +19,25 @@ pub struct Args {
     // set playing piece to white (default=black) if possible
     #[arg(long, short)]
     white_piece: bool,
+    // save the game transcript to file once the game ends (client only)
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    // join the open game with this id instead of creating one (client only)
+    #[arg(long, short)]
+    pub join: Option<u32>,
+    // print the games on the server and exit (client only)
+    #[arg(long, short)]
+    pub list: bool,
+    // watch the game with this id without playing (client only)
+    #[arg(long, short)]
+    pub spectate: Option<u32>,
+    // seconds a dropped player has to reconnect to their game (server only)
+    #[arg(long, default_value_t = 60)]
+    pub grace: u64,
+    // clock for every game: sudden:SECS, fischer:SECS+INC or
+    // byoyomi:SECS+PERIODxCOUNT (default=untimed, server only)
+    #[arg(long, short)]
+    pub time_control: Option<TimeControl>,
 }
 
 impl Args {
@@ -22,3 +49,152 @@ impl Args {
         !self.white_piece
     }
 }
+
+#[derive(Debug, Parser)]
+pub struct EngineArgs {
+    // search depth in plies
+    #[arg(long, short, default_value_t = 8)]
+    pub depth: u8,
+    // milliseconds per move, searching as deep as time allows
+    #[arg(long, short)]
+    pub time: Option<u64>,
+    // evaluation weights file (default=built in weights)
+    #[arg(long, short)]
+    pub weights: Option<PathBuf>,
+}
+
+impl EngineArgs {
+    pub fn limits(&self) -> SearchLimits {
+        match self.time {
+            Some(millis) => SearchLimits::time(Duration::from_millis(millis)),
+            None => SearchLimits::depth(self.depth),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct MatchArgs {
+    // first contestant: ai, ai:depth=N, ai:time=MS, ai:weights=PATH,
+    // ai:book=PATH or engine:COMMAND, settings of ai are separated by commas
+    pub first: Contestant,
+    // second contestant, same format as the first
+    pub second: Contestant,
+    // number of games, colors alternate every game
+    #[arg(long, short, default_value_t = 10)]
+    pub games: usize,
+    // file with one opening transcript per line, each one is played with
+    // both colors (default=standard start)
+    #[arg(long, short)]
+    pub openings: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TuiArgs {
+    // who plays: two players at this terminal, one against the ai, or one
+    // on a server
+    #[arg(long, short, value_enum, default_value_t = TuiMode::Local)]
+    pub mode: TuiMode,
+    // play white against the ai or on the server (default=black)
+    #[arg(long, short)]
+    pub white_piece: bool,
+    // search depth of the ai in plies
+    #[arg(long, short, default_value_t = 6)]
+    pub depth: u8,
+    // server host address (network only)
+    #[arg(long, short, default_value = "127.0.0.1")]
+    address: String,
+    // server host port (network only)
+    #[arg(long, short, default_value_t = 3000)]
+    port: u16,
+    // join the open game with this id instead of creating one (network only)
+    #[arg(long, short)]
+    pub join: Option<u32>,
+}
+
+impl TuiArgs {
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.address, self.port)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TuiMode {
+    Local,
+    Ai,
+    Network,
+}
+
+#[derive(Debug, Parser)]
+pub struct DebuggerArgs {
+    // position file saved to and loaded from, loaded at start if it exists
+    #[arg(default_value = "position.txt")]
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct StudyArgs {
+    #[command(subcommand)]
+    pub command: StudyCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StudyCommand {
+    // print game count, average score and opening frequencies of a database
+    Stats {
+        path: PathBuf,
+        // database format (default=guessed from the file extension)
+        #[arg(long, short, value_enum)]
+        format: Option<DatabaseFormat>,
+        // number of most played openings to list
+        #[arg(long, short, default_value_t = 10)]
+        openings: usize,
+        // number of plies that make up an opening
+        #[arg(long, default_value_t = 4)]
+        plies: usize,
+    },
+    // build an opening book and print the opening name and book moves of a
+    // position
+    Book {
+        // games to build the book from, one transcript per line unless a
+        // database format is given
+        path: PathBuf,
+        #[arg(long, short, value_enum)]
+        format: Option<DatabaseFormat>,
+        // number of plies of every game that go into the book
+        #[arg(long, default_value_t = DEFAULT_PLIES)]
+        plies: usize,
+        // transcript of the moves leading to the position (default=start)
+        #[arg(long, short, default_value = "")]
+        moves: String,
+    },
+    // count the move tree leaves depth plies deep to check move generation
+    Perft {
+        depth: u32,
+        // position file to start from, as saved by the debugger
+        // (default=start)
+        #[arg(long, short)]
+        position: Option<PathBuf>,
+        // transcript of the moves played from the starting position
+        #[arg(long, short, default_value = "")]
+        moves: String,
+        // print the count below every move of the position
+        #[arg(long, short)]
+        divide: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DatabaseFormat {
+    Ggf,
+    Wthor,
+}
+
+impl DatabaseFormat {
+    // .wtb files are WTHOR, anything else is read as GGF
+    pub fn from_path(path: &Path) -> DatabaseFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("wtb") => DatabaseFormat::Wthor,
+            _ => DatabaseFormat::Ggf,
+        }
+    }
+}
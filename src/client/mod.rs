@@ -0,0 +1,275 @@
+use std::{
+    io,
+    net::{Shutdown, TcpStream},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::clock::Remaining;
+use crate::game::{Action, Side};
+use crate::protocol::{read_message, write_message, GameInfo, Message};
+
+// How long a dropped client keeps trying to get back into its game
+const RECONNECT_TIME: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// Last clocks sent by the server, counted down locally in between
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockView {
+    pub black: Remaining,
+    pub white: Remaining,
+    pub period: Duration,
+    // When the side to move's time started running, None while paused
+    pub since: Option<Instant>,
+}
+
+impl ClockView {
+    // Clocks as just sent by the server, running from now
+    pub fn new(black: Remaining, white: Remaining, period: Duration) -> ClockView {
+        ClockView {
+            black,
+            white,
+            period,
+            since: Some(Instant::now()),
+        }
+    }
+
+    pub fn remaining(&self, side: Side, running: Option<Side>) -> Remaining {
+        let stored = match side {
+            Side::Black => self.black,
+            Side::White => self.white,
+        };
+        match self.since {
+            Some(since) if running == Some(side) => stored
+                .after(since.elapsed(), self.period)
+                .unwrap_or(Remaining {
+                    time: Duration::ZERO,
+                    periods: 0,
+                }),
+            _ => stored,
+        }
+    }
+
+    // Stops counting down the running side, as the server does while the
+    // game is paused, a new reading follows once it goes on
+    pub fn pause(&mut self, running: Side) {
+        let remaining = self.remaining(running, Some(running));
+        match running {
+            Side::Black => self.black = remaining,
+            Side::White => self.white = remaining,
+        }
+        self.since = None;
+    }
+}
+
+// Request joining an open game of the list where the given side is free, or
+// opening one if there is none
+pub fn find_game(games: &[GameInfo], side: Side) -> Message {
+    let open = games
+        .iter()
+        .find(|game| game.guest.is_none() && game.side == side.opponent());
+    match open {
+        Some(game) => Message::JoinGame(game.id),
+        None => Message::CreateGame(side),
+    }
+}
+
+// Reads messages on a thread of its own so front-ends keep drawing while
+// waiting for the server
+fn listen(stream: &TcpStream) -> io::Result<Receiver<io::Result<Message>>> {
+    let mut stream = stream.try_clone()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let message = read_message(&mut stream);
+        let lost = message.is_err();
+        if sender.send(message).is_err() || lost {
+            break;
+        }
+    });
+    Ok(receiver)
+}
+
+// Connection to a game server, shared by the client front-ends
+pub struct Link {
+    stream: TcpStream,
+    incoming: Receiver<io::Result<Message>>,
+    addr: String,
+    // Name the server introduced itself with
+    pub server: String,
+    // Token to resume the game with after losing the connection
+    session: Option<u64>,
+}
+
+impl Link {
+    // Connects and says hello with the given program name
+    pub fn connect(addr: String, name: &str) -> io::Result<Link> {
+        let stream = TcpStream::connect(&addr)?;
+        let mut link = Link {
+            incoming: listen(&stream)?,
+            stream,
+            addr,
+            server: String::new(),
+            session: None,
+        };
+        link.send(&Message::Hello {
+            name: name.to_string(),
+        })?;
+        loop {
+            match link.read()? {
+                Message::Hello { name } => link.server = name,
+                Message::Session(token) => {
+                    link.session = Some(token);
+                    return Ok(link);
+                }
+                message => return Err(io::Error::other(format!("unexpected {message:?}"))),
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        write_message(&mut self.stream, message)
+    }
+
+    // Waits for the next message
+    pub fn read(&mut self) -> io::Result<Message> {
+        self.incoming
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::other("connection closed")))
+    }
+
+    // Next message if one arrived already
+    pub fn try_read(&mut self) -> Option<io::Result<Message>> {
+        match self.incoming.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(io::Error::other("connection closed"))),
+        }
+    }
+
+    // Waits for the server to start the game, other messages are skipped.
+    // Created is told the id of a game opened for the client
+    pub fn wait_for_game(&mut self, mut created: impl FnMut(u32)) -> io::Result<Side> {
+        loop {
+            match self.read()? {
+                Message::ColorAssigned(side) => return Ok(side),
+                Message::GameCreated(id) => created(id),
+                Message::Error(error) => return Err(io::Error::other(error)),
+                _ => {}
+            }
+        }
+    }
+
+    // Opens a new connection and takes the seat back, returning the server's
+    // moves of the game. Refusals are retried too, the server may be back
+    // or see the old connection drop before the time is up
+    pub fn reconnect(&mut self) -> io::Result<Vec<Action>> {
+        let token = self
+            .session
+            .ok_or_else(|| io::Error::other("no session to resume"))?;
+        let deadline = Instant::now() + RECONNECT_TIME;
+        loop {
+            match self.resume(token) {
+                Ok(moves) => return Ok(moves),
+                Err(error) if Instant::now() >= deadline => return Err(error),
+                Err(_) => thread::sleep(RECONNECT_DELAY),
+            }
+        }
+    }
+
+    // One attempt at resuming the session on a new connection
+    fn resume(&mut self, token: u64) -> io::Result<Vec<Action>> {
+        // Also ends the reader thread of the old connection
+        let _ = self.stream.shutdown(Shutdown::Both);
+        self.stream = TcpStream::connect(&self.addr)?;
+        self.incoming = listen(&self.stream)?;
+        self.send(&Message::Resume(token))?;
+        loop {
+            match self.read()? {
+                Message::History { moves, .. } => return Ok(moves),
+                Message::Error(error) => return Err(io::Error::other(error)),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_find_game() {
+        let game = |id, side, guest: Option<&str>| GameInfo {
+            id,
+            host: "host".to_string(),
+            side,
+            guest: guest.map(String::from),
+        };
+        let games = [
+            game(0, Side::White, Some("guest")),
+            game(1, Side::Black, None),
+            game(2, Side::White, None),
+        ];
+        assert_eq!(find_game(&games, Side::Black), Message::JoinGame(2));
+        assert_eq!(find_game(&games, Side::White), Message::JoinGame(1));
+        assert_eq!(
+            find_game(&games[..1], Side::Black),
+            Message::CreateGame(Side::Black)
+        );
+    }
+
+    #[test]
+    fn test_reconnect_retries() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let moves = vec![Action::Pass];
+        let history = moves.clone();
+        thread::spawn(move || {
+            let mut streams = server.incoming().map(Result::unwrap);
+            let mut hello = streams.next().unwrap();
+            read_message(&mut hello).unwrap();
+            let name = "test".to_string();
+            write_message(&mut hello, &Message::Hello { name }).unwrap();
+            write_message(&mut hello, &Message::Session(7)).unwrap();
+            // The first resume comes before the server gave up the old seat
+            let mut early = streams.next().unwrap();
+            assert_eq!(read_message(&mut early).unwrap(), Message::Resume(7));
+            let error = Message::Error("no game to resume".to_string());
+            write_message(&mut early, &error).unwrap();
+            let mut late = streams.next().unwrap();
+            assert_eq!(read_message(&mut late).unwrap(), Message::Resume(7));
+            let side = Side::Black;
+            write_message(&mut late, &Message::History { side, moves }).unwrap();
+        });
+        let mut link = Link::connect(addr, "client").unwrap();
+        assert_eq!(link.reconnect().unwrap(), history);
+    }
+
+    #[test]
+    fn test_clock_view() {
+        let minute = Remaining {
+            time: Duration::from_secs(60),
+            periods: 0,
+        };
+        let mut clock = ClockView::new(minute, minute, Duration::ZERO);
+        clock.since = Some(Instant::now() - Duration::from_secs(10));
+        let left = clock.remaining(Side::Black, Some(Side::Black)).time;
+        assert!(left <= Duration::from_secs(50) && left > Duration::from_secs(45));
+        // Only the running side counts down
+        assert_eq!(clock.remaining(Side::White, Some(Side::Black)), minute);
+        assert_eq!(clock.remaining(Side::Black, None), minute);
+        clock.pause(Side::Black);
+        assert_eq!(clock.since, None);
+        assert!(clock.black.time <= Duration::from_secs(50));
+        assert_eq!(clock.remaining(Side::Black, Some(Side::Black)), clock.black);
+        // Out of time shows zero rather than going negative
+        clock.since = Some(Instant::now() - Duration::from_secs(120));
+        assert_eq!(
+            clock.remaining(Side::White, Some(Side::White)).time,
+            Duration::ZERO
+        );
+    }
+}
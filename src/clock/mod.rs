@@ -0,0 +1,431 @@
+use std::{
+    fmt, io,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::game::Side;
+
+// How much thinking time each player gets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeControl {
+    // Fixed time for the whole game
+    SuddenDeath {
+        total: Duration,
+    },
+    // Increment added after every move
+    Fischer {
+        total: Duration,
+        increment: Duration,
+    },
+    // Once the main time is used up every move has to be made within a
+    // period, running over one uses it up and the game is lost when none are
+    // left
+    ByoYomi {
+        main: Duration,
+        period: Duration,
+        periods: u32,
+    },
+}
+
+fn seconds(text: &str) -> io::Result<Duration> {
+    text.trim()
+        .parse()
+        .map(Duration::from_secs)
+        .map_err(|_| invalid_input(format!("{text:?} is not a number of seconds")))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// Parses "sudden:300", "fischer:300+5" or "byoyomi:600+30x5", all in seconds
+impl FromStr for TimeControl {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<TimeControl> {
+        let (kind, value) = text
+            .split_once(':')
+            .ok_or_else(|| invalid_input(format!("{text:?} has no time control kind")))?;
+        match kind.trim() {
+            "sudden" => Ok(TimeControl::SuddenDeath {
+                total: seconds(value)?,
+            }),
+            "fischer" => {
+                let (total, increment) = value
+                    .split_once('+')
+                    .ok_or_else(|| invalid_input("expected total+increment".to_string()))?;
+                Ok(TimeControl::Fischer {
+                    total: seconds(total)?,
+                    increment: seconds(increment)?,
+                })
+            }
+            "byoyomi" => {
+                let (main, rest) = value
+                    .split_once('+')
+                    .ok_or_else(|| invalid_input("expected main+periodxcount".to_string()))?;
+                let (period, periods) = rest
+                    .split_once('x')
+                    .ok_or_else(|| invalid_input("expected main+periodxcount".to_string()))?;
+                let periods = periods
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_input(format!("{periods:?} is not a period count")))?;
+                Ok(TimeControl::ByoYomi {
+                    main: seconds(main)?,
+                    period: seconds(period)?,
+                    periods,
+                })
+            }
+            kind => Err(invalid_input(format!("unknown time control {kind:?}"))),
+        }
+    }
+}
+
+impl TimeControl {
+    fn initial(&self) -> Remaining {
+        match *self {
+            TimeControl::SuddenDeath { total } | TimeControl::Fischer { total, .. } => Remaining {
+                time: total,
+                periods: 0,
+            },
+            TimeControl::ByoYomi { main, periods, .. } => Remaining {
+                time: main,
+                periods,
+            },
+        }
+    }
+
+    // Length of a byo-yomi period, zero for the other controls
+    pub fn period(&self) -> Duration {
+        match *self {
+            TimeControl::ByoYomi { period, .. } => period,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+// Time left in the current phase, main time or the current byo-yomi period,
+// and the byo-yomi periods left including the current one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Remaining {
+    pub time: Duration,
+    pub periods: u32,
+}
+
+impl Remaining {
+    const OUT: Remaining = Remaining {
+        time: Duration::ZERO,
+        periods: 0,
+    };
+
+    // What is left after thinking for elapsed with byo-yomi periods of the
+    // given length, None once out of time
+    pub fn after(self, elapsed: Duration, period: Duration) -> Option<Remaining> {
+        if elapsed < self.time {
+            return Some(Remaining {
+                time: self.time - elapsed,
+                ..self
+            });
+        }
+        if period.is_zero() {
+            return None;
+        }
+        let over = (elapsed - self.time).as_nanos();
+        let used = over / period.as_nanos();
+        if used >= self.periods as u128 {
+            return None;
+        }
+        Some(Remaining {
+            time: period - Duration::from_nanos((over % period.as_nanos()) as u64),
+            periods: self.periods - used as u32,
+        })
+    }
+}
+
+// Minutes and seconds, followed by the periods left in byo-yomi
+impl fmt::Display for Remaining {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Rounded up so a clock showing 0:00 has really run out
+        let seconds = self.time.as_millis().div_ceil(1000);
+        write!(f, "{}:{:02}", seconds / 60, seconds % 60)?;
+        if self.periods > 0 {
+            write!(f, " ({})", self.periods)?;
+        }
+        Ok(())
+    }
+}
+
+// Chess clock for both sides, every call is given the current time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clock {
+    control: TimeControl,
+    // Main time left, zero once a side is in byo-yomi
+    black: Remaining,
+    white: Remaining,
+    // Side whose time is running and since when
+    running: Option<(Side, Instant)>,
+    // Side whose turn is paused and how much of it was used, going on where
+    // it stopped so a dropped connection never buys time
+    paused: Option<(Side, Duration)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            black: control.initial(),
+            white: control.initial(),
+            running: None,
+            paused: None,
+        }
+    }
+
+    fn stored(&mut self, side: Side) -> &mut Remaining {
+        match side {
+            Side::Black => &mut self.black,
+            Side::White => &mut self.white,
+        }
+    }
+
+    pub fn remaining(&self, side: Side, now: Instant) -> Remaining {
+        let stored = match side {
+            Side::Black => self.black,
+            Side::White => self.white,
+        };
+        let elapsed = match (self.running, self.paused) {
+            (Some((running, since)), _) if running == side => now.saturating_duration_since(since),
+            (_, Some((paused, spent))) if paused == side => spent,
+            _ => Duration::ZERO,
+        };
+        stored
+            .after(elapsed, self.control.period())
+            .unwrap_or(Remaining::OUT)
+    }
+
+    pub fn period(&self) -> Duration {
+        self.control.period()
+    }
+
+    // Side to move whose time ran out
+    pub fn flagged(&self, now: Instant) -> Option<Side> {
+        let (side, _) = self.running?;
+        (self.remaining(side, now) == Remaining::OUT).then_some(side)
+    }
+
+    // Starts the side's time, a paused turn of the side goes on with the
+    // time it already used
+    pub fn start(&mut self, side: Side, now: Instant) {
+        let spent = match self.paused.take() {
+            Some((paused, spent)) if paused == side => spent,
+            _ => Duration::ZERO,
+        };
+        self.running = Some((side, now.checked_sub(spent).unwrap_or(now)));
+    }
+
+    // Stops the clock of the side that just moved and charges it for the
+    // time it used, false if it was already out of time
+    pub fn stop(&mut self, now: Instant) -> bool {
+        let Some((side, since)) = self.running.take() else {
+            return true;
+        };
+        let control = self.control;
+        let stored = self.stored(side);
+        let elapsed = now.saturating_duration_since(since);
+        let Some(left) = stored.after(elapsed, control.period()) else {
+            *stored = Remaining::OUT;
+            return false;
+        };
+        *stored = match control {
+            _ if elapsed >= stored.time => Remaining {
+                // Whatever happens in byo-yomi the next turn gets a full period
+                time: Duration::ZERO,
+                periods: left.periods,
+            },
+            TimeControl::Fischer { increment, .. } => Remaining {
+                time: left.time + increment,
+                ..left
+            },
+            _ => left,
+        };
+        true
+    }
+
+    // Stops the running time without a move, as when the game is paused,
+    // returning whose time it was. Nothing is charged until the turn ends
+    pub fn pause(&mut self, now: Instant) -> Option<Side> {
+        let (side, since) = self.running.take()?;
+        self.paused = Some((side, now.saturating_duration_since(since)));
+        Some(side)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "sudden:300".parse::<TimeControl>().unwrap(),
+            TimeControl::SuddenDeath { total: secs(300) }
+        );
+        assert_eq!(
+            "fischer:300+5".parse::<TimeControl>().unwrap(),
+            TimeControl::Fischer {
+                total: secs(300),
+                increment: secs(5)
+            }
+        );
+        assert_eq!(
+            "byoyomi:600+30x5".parse::<TimeControl>().unwrap(),
+            TimeControl::ByoYomi {
+                main: secs(600),
+                period: secs(30),
+                periods: 5
+            }
+        );
+        assert!("fischer:300".parse::<TimeControl>().is_err());
+        assert!("hourglass:60".parse::<TimeControl>().is_err());
+        assert!("sudden:five".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn test_sudden_death() {
+        let now = Instant::now();
+        let mut clock = Clock::new(TimeControl::SuddenDeath { total: secs(60) });
+        clock.start(Side::Black, now);
+        assert!(clock.stop(now + secs(20)));
+        clock.start(Side::White, now + secs(20));
+        assert_eq!(clock.remaining(Side::Black, now + secs(50)).time, secs(40));
+        assert_eq!(clock.remaining(Side::White, now + secs(50)).time, secs(30));
+        assert_eq!(clock.flagged(now + secs(79)), None);
+        assert_eq!(clock.flagged(now + secs(80)), Some(Side::White));
+        assert!(!clock.stop(now + secs(81)));
+    }
+
+    #[test]
+    fn test_fischer() {
+        let now = Instant::now();
+        let mut clock = Clock::new(TimeControl::Fischer {
+            total: secs(10),
+            increment: secs(5),
+        });
+        clock.start(Side::Black, now);
+        assert!(clock.stop(now + secs(8)));
+        assert_eq!(clock.remaining(Side::Black, now).time, secs(7));
+    }
+
+    #[test]
+    fn test_byo_yomi() {
+        let now = Instant::now();
+        let mut clock = Clock::new(TimeControl::ByoYomi {
+            main: secs(10),
+            period: secs(5),
+            periods: 3,
+        });
+        // Main time runs out two seconds into the first period
+        clock.start(Side::Black, now);
+        let remaining = clock.remaining(Side::Black, now + secs(12));
+        assert_eq!(
+            remaining,
+            Remaining {
+                time: secs(3),
+                periods: 3
+            }
+        );
+        assert!(clock.stop(now + secs(12)));
+        assert_eq!(
+            clock.remaining(Side::Black, now),
+            Remaining {
+                time: secs(5),
+                periods: 3
+            }
+        );
+        // Running over one period uses it up
+        clock.start(Side::Black, now);
+        assert!(clock.stop(now + secs(7)));
+        assert_eq!(
+            clock.remaining(Side::Black, now),
+            Remaining {
+                time: secs(5),
+                periods: 2
+            }
+        );
+        clock.start(Side::Black, now);
+        assert_eq!(clock.flagged(now + secs(9)), None);
+        assert_eq!(clock.flagged(now + secs(10)), Some(Side::Black));
+        assert!(!clock.stop(now + secs(10)));
+    }
+
+    #[test]
+    fn test_pause() {
+        let now = Instant::now();
+        let mut clock = Clock::new(TimeControl::Fischer {
+            total: secs(10),
+            increment: secs(5),
+        });
+        clock.start(Side::White, now);
+        assert_eq!(clock.pause(now + secs(3)), Some(Side::White));
+        // No increment without a move and nothing runs while paused
+        assert_eq!(clock.remaining(Side::White, now + secs(60)).time, secs(7));
+        assert_eq!(clock.flagged(now + secs(60)), None);
+        assert_eq!(clock.pause(now + secs(60)), None);
+    }
+
+    #[test]
+    fn test_pause_in_byo_yomi() {
+        let now = Instant::now();
+        let mut clock = Clock::new(TimeControl::ByoYomi {
+            main: secs(10),
+            period: secs(5),
+            periods: 3,
+        });
+        // Paused two seconds into the first period
+        clock.start(Side::Black, now);
+        clock.pause(now + secs(12));
+        let part = Remaining {
+            time: secs(3),
+            periods: 3,
+        };
+        assert_eq!(clock.remaining(Side::Black, now + secs(60)), part);
+        // Resuming keeps the part of the period left instead of a full one
+        clock.start(Side::Black, now + secs(60));
+        assert_eq!(clock.remaining(Side::Black, now + secs(60)), part);
+        clock.pause(now + secs(62));
+        clock.start(Side::Black, now + secs(90));
+        assert_eq!(
+            clock.remaining(Side::Black, now + secs(92)),
+            Remaining {
+                time: secs(4),
+                periods: 2
+            }
+        );
+        assert!(clock.stop(now + secs(92)));
+        assert_eq!(
+            clock.remaining(Side::Black, now),
+            Remaining {
+                time: secs(5),
+                periods: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let remaining = Remaining {
+            time: Duration::from_millis(65_500),
+            periods: 0,
+        };
+        assert_eq!(remaining.to_string(), "1:06");
+        let remaining = Remaining {
+            time: secs(30),
+            periods: 3,
+        };
+        assert_eq!(remaining.to_string(), "0:30 (3)");
+    }
+}
@@ -0,0 +1,403 @@
+use crate::game::{GameState, PieceConfig, Side};
+
+pub enum Move {
+    Position(u64),
+    Board,
+    // Replaces the position without playing a move, e.g. after catching up
+    // with a server
+    Reset(PieceConfig),
+    // No move yet, the player is asked again on the next step
+    Pending,
+}
+
+pub trait Player {
+    fn play_move(&mut self, config: &PieceConfig) -> Move;
+    fn enemy_move(&mut self, current_move: u64);
+}
+
+pub type CaptureHandler = Box<dyn Fn(&PieceConfig, u64) -> PieceConfig>;
+pub type ValidHandler = Box<dyn Fn(&PieceConfig, u64) -> bool>;
+
+// What a front-end tells the controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    // Asks the side to move for its move, sent once a frame
+    Poll,
+    // Square picked on the board, played when the side to move leaves its
+    // move to the board and the square is valid
+    Select(u64),
+    // Shows the position after the given number of moves of the history
+    Jump(usize),
+    Undo,
+    Redo,
+    // Position set up other than by moves, starting a new history
+    SetPosition(PieceConfig),
+}
+
+// What changed, for the front-end to show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Played(Side, u64),
+    // The side had no move after the last one
+    Passed(Side),
+    GameOver { black: u32, white: u32 },
+    // Selected square the side to move cannot play
+    Rejected(u64),
+    // Position replaced by a player or the front-end
+    Reset,
+    // Position of the history now shown
+    Jumped(usize),
+}
+
+// Runs a game between two players without drawing anything, front-ends turn
+// their input into steps and show the events
+pub struct Controller {
+    config: PieceConfig,
+    capture: CaptureHandler,
+    valid: ValidHandler,
+    black: Box<dyn Player>,
+    white: Box<dyn Player>,
+    // Every position of the game so far and the moves between them, the
+    // ones after the cursor are there to redo
+    positions: Vec<PieceConfig>,
+    moves: Vec<u64>,
+    cursor: usize,
+}
+
+impl Controller {
+    pub fn new(
+        config: PieceConfig,
+        capture: CaptureHandler,
+        valid: ValidHandler,
+        black: Box<dyn Player>,
+        white: Box<dyn Player>,
+    ) -> Controller {
+        Controller {
+            config,
+            capture,
+            valid,
+            black,
+            white,
+            positions: vec![config],
+            moves: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    pub fn config(&self) -> &PieceConfig {
+        &self.config
+    }
+
+    // All positions of the history including undone ones
+    pub fn positions(&self) -> &[PieceConfig] {
+        &self.positions
+    }
+
+    // All moves of the history including undone ones
+    pub fn moves(&self) -> &[u64] {
+        &self.moves
+    }
+
+    // Number of moves played to reach the position shown
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn step(&mut self, input: Input) -> Vec<Event> {
+        match input {
+            Input::Poll => self.poll(None),
+            Input::Select(position) => self.poll(Some(position)),
+            Input::Jump(index) => self.jump(index),
+            Input::Undo if self.cursor > 0 => self.jump(self.cursor - 1),
+            Input::Undo => Vec::new(),
+            Input::Redo => self.jump(self.cursor + 1),
+            Input::SetPosition(config) => {
+                self.config = config;
+                self.restart();
+                vec![Event::Reset]
+            }
+        }
+    }
+
+    fn poll(&mut self, selected: Option<u64>) -> Vec<Event> {
+        let side = GameState::from(self.config).side_to_move();
+        let (player, opponent) = match side {
+            Side::Black => (&mut self.black, &mut self.white),
+            Side::White => (&mut self.white, &mut self.black),
+        };
+        let position = match player.play_move(&self.config) {
+            Move::Position(position) => position,
+            Move::Board => match selected {
+                Some(position) if (self.valid)(&self.config, position) => position,
+                Some(position) => return vec![Event::Rejected(position)],
+                None => return Vec::new(),
+            },
+            Move::Reset(config) => {
+                self.config = config;
+                self.restart();
+                return vec![Event::Reset];
+            }
+            Move::Pending => return Vec::new(),
+        };
+        opponent.enemy_move(position);
+
+        let previous = self.config;
+        self.config = (self.capture)(&previous, position);
+        // Holding the button down keeps selecting the same square
+        if self.config == previous {
+            return Vec::new();
+        }
+        self.record(position);
+        let mut events = vec![Event::Played(side, position)];
+        let state = GameState::from(self.config);
+        if state.is_terminal() {
+            let (black, white) = state.score();
+            events.push(Event::GameOver { black, white });
+        } else if state.side_to_move() == side {
+            events.push(Event::Passed(side.opponent()));
+        }
+        events
+    }
+
+    fn jump(&mut self, index: usize) -> Vec<Event> {
+        if index >= self.positions.len() || index == self.cursor {
+            return Vec::new();
+        }
+        self.cursor = index;
+        self.config = self.positions[index];
+        vec![Event::Jumped(index)]
+    }
+
+    // A move from an earlier position drops the moves that were undone
+    fn record(&mut self, position: u64) {
+        self.positions.truncate(self.cursor + 1);
+        self.moves.truncate(self.cursor);
+        self.positions.push(self.config);
+        self.moves.push(position);
+        self.cursor += 1;
+    }
+
+    // Positions set up other than by moves start a new history
+    fn restart(&mut self) {
+        self.positions = vec![self.config];
+        self.moves.clear();
+        self.cursor = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use super::*;
+    use crate::game::Action;
+    use crate::notation::{parse_square, parse_transcript};
+    use crate::players::AiPlayer;
+    use crate::search::SearchLimits;
+
+    // Plays the given moves in order and remembers what it was told
+    struct Scripted {
+        moves: VecDeque<Move>,
+        told: Rc<RefCell<Vec<u64>>>,
+    }
+
+    impl Scripted {
+        fn new(moves: Vec<Move>) -> (Scripted, Rc<RefCell<Vec<u64>>>) {
+            let told = Rc::new(RefCell::new(Vec::new()));
+            let player = Scripted {
+                moves: moves.into(),
+                told: told.clone(),
+            };
+            (player, told)
+        }
+    }
+
+    impl Player for Scripted {
+        fn play_move(&mut self, _config: &PieceConfig) -> Move {
+            self.moves.pop_front().unwrap_or(Move::Board)
+        }
+
+        fn enemy_move(&mut self, current_move: u64) {
+            self.told.borrow_mut().push(current_move);
+        }
+    }
+
+    fn capture(config: &PieceConfig, position: u64) -> PieceConfig {
+        let state = GameState::from(*config)
+            .apply(Action::Place(position))
+            .unwrap();
+        match state.must_pass() {
+            true => state.apply(Action::Pass).unwrap().config,
+            false => state.config,
+        }
+    }
+
+    fn valid(config: &PieceConfig, position: u64) -> bool {
+        GameState::from(*config).move_mask() & position != 0
+    }
+
+    fn game(black: impl Player + 'static, white: impl Player + 'static) -> Controller {
+        Controller::new(
+            GameState::new().config,
+            Box::new(capture),
+            Box::new(valid),
+            Box::new(black),
+            Box::new(white),
+        )
+    }
+
+    // Both sides left to the board, as on a board for two people
+    fn local() -> Controller {
+        game(Scripted::new(vec![]).0, Scripted::new(vec![]).0)
+    }
+
+    fn select(controller: &mut Controller, square: &str) -> Vec<Event> {
+        controller.step(Input::Select(parse_square(square).unwrap()))
+    }
+
+    #[test]
+    fn test_scripted_game() {
+        // Shortest game, white is wiped out after 9 moves
+        let transcript = "f5d6c5f4e3f6g5e6e7";
+        let placements: Vec<u64> = parse_transcript(transcript)
+            .unwrap()
+            .into_iter()
+            .map(|action| match action {
+                Action::Place(position) => position,
+                Action::Pass => unreachable!(),
+            })
+            .collect();
+        let script = |side: usize| {
+            placements
+                .iter()
+                .skip(side)
+                .step_by(2)
+                .map(|&position| Move::Position(position))
+                .collect::<Vec<_>>()
+        };
+        let (black, told_black) = Scripted::new(script(0));
+        let (white, told_white) = Scripted::new(script(1));
+        let mut controller = game(black, white);
+        let mut events = Vec::new();
+        for _ in 0..20 {
+            events.extend(controller.step(Input::Poll));
+        }
+        assert_eq!(controller.moves(), placements);
+        assert_eq!(
+            events.last(),
+            Some(&Event::GameOver {
+                black: 13,
+                white: 0
+            })
+        );
+        assert_eq!(events[0], Event::Played(Side::Black, placements[0]));
+        assert_eq!(events[1], Event::Played(Side::White, placements[1]));
+        // Each player was told every move of the other
+        let told = |side: usize| placements.iter().skip(side).step_by(2).copied();
+        assert!(told_white.borrow().iter().copied().eq(told(0)));
+        assert!(told_black.borrow().iter().copied().eq(told(1)));
+    }
+
+    #[test]
+    fn test_ai_game() {
+        let limits = SearchLimits::depth(1);
+        let mut controller = game(AiPlayer::new(limits), AiPlayer::new(limits));
+        let mut over = None;
+        for _ in 0..100 {
+            for event in controller.step(Input::Poll) {
+                if let Event::GameOver { black, white } = event {
+                    over = Some((black, white));
+                }
+            }
+        }
+        let state = GameState::from(*controller.config());
+        assert!(state.is_terminal());
+        assert_eq!(over, Some(state.score()));
+        assert_eq!(controller.positions().len(), controller.moves().len() + 1);
+    }
+
+    #[test]
+    fn test_select() {
+        let mut controller = local();
+        assert_eq!(controller.step(Input::Poll), []);
+        let a1 = parse_square("a1").unwrap();
+        assert_eq!(select(&mut controller, "a1"), [Event::Rejected(a1)]);
+        let f5 = parse_square("f5").unwrap();
+        assert_eq!(
+            select(&mut controller, "f5"),
+            [Event::Played(Side::Black, f5)]
+        );
+        assert!(!controller.config().blacks_play);
+        // Players with a move of their own ignore the selection
+        let d6 = parse_square("d6").unwrap();
+        let (white, _) = Scripted::new(vec![Move::Pending, Move::Position(d6)]);
+        let mut controller = game(Scripted::new(vec![]).0, white);
+        select(&mut controller, "f5");
+        assert_eq!(select(&mut controller, "f4"), []);
+        assert_eq!(
+            select(&mut controller, "f4"),
+            [Event::Played(Side::White, d6)]
+        );
+    }
+
+    #[test]
+    fn test_pass() {
+        // White has no move after black takes a1
+        let squares = |names: &[&str]| names.iter().map(|name| parse_square(name).unwrap()).sum();
+        let config = PieceConfig {
+            black_pieces: squares(&["c1", "h8"]),
+            white_pieces: squares(&["b1", "f8", "g8"]),
+            blacks_play: true,
+        };
+        let mut controller = Controller::new(
+            config,
+            Box::new(capture),
+            Box::new(valid),
+            Box::new(Scripted::new(vec![]).0),
+            Box::new(Scripted::new(vec![]).0),
+        );
+        let a1 = parse_square("a1").unwrap();
+        assert_eq!(
+            select(&mut controller, "a1"),
+            [Event::Played(Side::Black, a1), Event::Passed(Side::White)]
+        );
+        assert!(controller.config().blacks_play);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut controller = local();
+        select(&mut controller, "f5");
+        select(&mut controller, "d6");
+        let after = *controller.config();
+        controller.step(Input::Undo);
+        assert_eq!(controller.step(Input::Undo), [Event::Jumped(0)]);
+        assert_eq!(*controller.config(), GameState::new().config);
+        assert_eq!(controller.step(Input::Undo), []);
+        controller.step(Input::Redo);
+        controller.step(Input::Redo);
+        assert_eq!(controller.step(Input::Redo), []);
+        assert_eq!(*controller.config(), after);
+        assert_eq!(controller.cursor(), 2);
+    }
+
+    #[test]
+    fn test_jump_and_branch() {
+        let mut controller = local();
+        for square in ["f5", "d6", "c3"] {
+            select(&mut controller, square);
+        }
+        controller.step(Input::Jump(1));
+        assert_eq!(controller.cursor(), 1);
+        // Playing from an earlier position drops the moves after it
+        select(&mut controller, "f6");
+        controller.step(Input::Redo);
+        assert_eq!(controller.cursor(), 2);
+        assert_eq!(controller.positions().len(), 3);
+        assert_eq!(controller.step(Input::Jump(10)), []);
+        let start = GameState::new().config;
+        assert_eq!(controller.step(Input::SetPosition(start)), [Event::Reset]);
+        assert_eq!(controller.positions(), [start]);
+        assert_eq!(controller.moves(), []);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use super::{invalid_data, GameRecord};
+use crate::bitboard_position;
+use crate::game::{Action, GameState, PieceConfig};
+use crate::notation::parse_square;
+
+// Streams games out of a GGF text file, each game is a "(;" ... ";)" block
+// of KEY[value] properties
+pub struct GgfReader<R: BufRead> {
+    reader: R,
+    finished: bool,
+}
+
+impl GgfReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(GgfReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> GgfReader<R> {
+    pub fn new(reader: R) -> Self {
+        GgfReader {
+            reader,
+            finished: false,
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = match self.reader.fill_buf()? {
+            [] => return Ok(None),
+            buffer => buffer[0],
+        };
+        self.reader.consume(1);
+        Ok(Some(byte))
+    }
+
+    // Text between "(;" and ";)", brackets are skipped over so values may
+    // hold either delimiter
+    fn next_game(&mut self) -> io::Result<Option<String>> {
+        let mut previous = 0;
+        loop {
+            match self.next_byte()? {
+                None => return Ok(None),
+                Some(b';') if previous == b'(' => break,
+                Some(byte) => previous = byte,
+            }
+        }
+        let mut game = Vec::new();
+        let mut in_value = false;
+        loop {
+            let byte = self
+                .next_byte()?
+                .ok_or_else(|| invalid_data("game is not closed".to_string()))?;
+            match byte {
+                b'[' => in_value = true,
+                b']' => in_value = false,
+                b')' if !in_value && game.last() == Some(&b';') => {
+                    game.pop();
+                    break;
+                }
+                _ => {}
+            }
+            game.push(byte);
+        }
+        String::from_utf8(game)
+            .map(Some)
+            .map_err(|_| invalid_data("game is not valid UTF-8".to_string()))
+    }
+}
+
+// Splits "PB[name]B[f5]" into ("PB", "name") and ("B", "f5")
+fn properties(text: &str) -> io::Result<Vec<(&str, &str)>> {
+    let mut properties = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let key = rest[..open].trim();
+        let close = rest[open..]
+            .find(']')
+            .ok_or_else(|| invalid_data(format!("value of {key} is not closed")))?;
+        properties.push((key, &rest[open + 1..open + close]));
+        rest = &rest[open + close + 1..];
+    }
+    Ok(properties)
+}
+
+// "+12.000" or "-4:r", only the disc differential is kept
+fn parse_result(value: &str) -> Option<i32> {
+    let score = value.split(':').next()?.trim();
+    score.parse::<f64>().ok().map(|score| score.round() as i32)
+}
+
+// Board size followed by 64 squares and the side to move, "*" is black,
+// "O" is white and "-" is empty
+fn parse_board(value: &str) -> io::Result<GameState> {
+    let invalid = || invalid_data(format!("invalid board {value:?}"));
+    let mut tokens = value.split_whitespace();
+    if tokens.next() != Some("8") {
+        return Err(invalid());
+    }
+    let squares: Vec<char> = tokens.flat_map(str::chars).collect();
+    let (side, squares) = squares.split_last().ok_or_else(invalid)?;
+    if squares.len() != 64 {
+        return Err(invalid());
+    }
+    let mut config = PieceConfig {
+        white_pieces: 0,
+        black_pieces: 0,
+        blacks_play: *side == '*',
+    };
+    for (index, square) in squares.iter().enumerate() {
+        let position = bitboard_position(index as u8 / 8, index as u8 % 8);
+        match square {
+            '*' => config.black_pieces |= position,
+            'O' => config.white_pieces |= position,
+            '-' => {}
+            _ => return Err(invalid()),
+        }
+    }
+    if !matches!(side, '*' | 'O') {
+        return Err(invalid());
+    }
+    Ok(GameState::from(config))
+}
+
+// "f5/1.23/0.05" carries evaluation and time after the square
+fn parse_move(value: &str) -> io::Result<Action> {
+    let square = value.split('/').next().unwrap_or_default().trim();
+    if square.eq_ignore_ascii_case("pa") {
+        return Ok(Action::Pass);
+    }
+    parse_square(square)
+        .map(Action::Place)
+        .ok_or_else(|| invalid_data(format!("{square:?} is not a square")))
+}
+
+fn parse_game(text: &str) -> io::Result<GameRecord> {
+    let mut black = String::new();
+    let mut white = String::new();
+    let mut result = None;
+    let mut start = GameState::new();
+    let mut moves = Vec::new();
+    for (key, value) in properties(text)? {
+        match key {
+            "PB" => black = value.to_string(),
+            "PW" => white = value.to_string(),
+            "RE" => result = parse_result(value),
+            // Random starts such as "8r" are still played on an 8x8 board
+            "TY" if !value.starts_with('8') => {
+                return Err(invalid_data(format!("unsupported game type {value:?}")))
+            }
+            "BO" => start = parse_board(value)?,
+            "B" | "W" => moves.push(parse_move(value)?),
+            _ => {}
+        }
+    }
+    GameRecord::new(black, white, result, start, moves)
+}
+
+impl<R: BufRead> Iterator for GgfReader<R> {
+    type Item = io::Result<GameRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_game() {
+            Ok(Some(text)) => Some(parse_game(&text)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(error) => {
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str =
+        "8 -------- -------- -------- ---O*--- ---*O--- -------- -------- -------- *";
+
+    #[test]
+    fn test_read_games() {
+        let text = format!(
+            "(;GM[Othello]PC[GGS/os]PB[alice]PW[bob]RE[+64.000]TY[8]BO[{START}]\
+             B[f5//0.01]W[d6]B[c5]W[f4]B[e3]W[f6]B[g5]W[e6]B[e7];)\n\
+             (;GM[Othello]PB[carol]PW[dave]RE[-2.000:r]TY[8r]BO[{START}]B[F5]W[pa];)"
+        );
+        let mut reader = GgfReader::new(text.as_bytes());
+
+        let game = reader.next().unwrap().unwrap();
+        assert_eq!((game.black.as_str(), game.white.as_str()), ("alice", "bob"));
+        assert_eq!(game.result, Some(64));
+        assert_eq!(game.transcript(), "f5d6c5f4e3f6g5e6e7");
+        assert!(game.final_state().is_terminal());
+
+        // Passing with moves available is not legal
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_custom_board() {
+        let board = "8 *O------ -------- -------- -------- -------- -------- -------- -------- O";
+        let text = format!("(;PB[a]PW[b]BO[{board}]B[c1];)");
+        let game = GgfReader::new(text.as_bytes()).next().unwrap().unwrap();
+        assert!(!game.start.config.blacks_play);
+        assert_eq!(game.transcript(), "pac1");
+        assert_eq!(game.result, None);
+    }
+
+    #[test]
+    fn test_invalid_games() {
+        assert!(parse_game("TY[10]").is_err());
+        assert!(parse_game("BO[8 ---- *]").is_err());
+        assert!(parse_game("B[z9]").is_err());
+        assert!(parse_game("PB[unclosed").is_err());
+        let mut reader = GgfReader::new("(;PB[a]B[f5]".as_bytes());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
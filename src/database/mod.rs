@@ -0,0 +1,178 @@
+use std::{collections::HashMap, io};
+
+use crate::game::{Action, GameState};
+use crate::notation::write_transcript;
+
+pub mod ggf;
+pub mod wthor;
+
+pub use ggf::GgfReader;
+pub use wthor::WthorReader;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// A validated game from a database, passes are always explicit in moves
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    pub black: String,
+    pub white: String,
+    // Black minus white discs as recorded in the database
+    pub result: Option<i32>,
+    pub start: GameState,
+    pub moves: Vec<Action>,
+}
+
+impl GameRecord {
+    // Replays the moves from start, filling in passes the record left out
+    pub fn new(
+        black: String,
+        white: String,
+        result: Option<i32>,
+        start: GameState,
+        played: impl IntoIterator<Item = Action>,
+    ) -> io::Result<GameRecord> {
+        let mut state = start;
+        let mut moves = Vec::new();
+        for (index, action) in played.into_iter().enumerate() {
+            if action != Action::Pass && state.must_pass() {
+                state = state.apply(Action::Pass).unwrap();
+                moves.push(Action::Pass);
+            }
+            state = state
+                .apply(action)
+                .ok_or_else(|| invalid_data(format!("move {} is not legal", index + 1)))?;
+            moves.push(action);
+        }
+        Ok(GameRecord {
+            black,
+            white,
+            result,
+            start,
+            moves,
+        })
+    }
+
+    // Every position of the game, starting with the initial one
+    pub fn positions(&self) -> impl Iterator<Item = GameState> + '_ {
+        let replayed = self.moves.iter().scan(self.start, |state, &action| {
+            *state = state.apply(action)?;
+            Some(*state)
+        });
+        std::iter::once(self.start).chain(replayed)
+    }
+
+    pub fn final_state(&self) -> GameState {
+        self.positions().last().unwrap()
+    }
+
+    pub fn transcript(&self) -> String {
+        write_transcript(&self.moves)
+    }
+}
+
+// Aggregate statistics over many games
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub games: usize,
+    // Games that could not be read or replayed
+    pub errors: usize,
+    total_result: i64,
+    openings: HashMap<String, usize>,
+}
+
+impl Summary {
+    // Counts a game, using its first plies moves as the opening and the
+    // final board when the record holds no result
+    pub fn add(&mut self, game: &GameRecord, plies: usize) {
+        self.games += 1;
+        let result = game.result.unwrap_or_else(|| {
+            let (black, white) = game.final_state().score();
+            black as i32 - white as i32
+        });
+        self.total_result += result as i64;
+        // Openings only compare between games from the standard start
+        if game.start == GameState::new() && game.moves.len() >= plies {
+            let opening = write_transcript(&game.moves[..plies]);
+            *self.openings.entry(opening).or_default() += 1;
+        }
+    }
+
+    pub fn add_error(&mut self) {
+        self.errors += 1;
+    }
+
+    // Average black minus white disc differential
+    pub fn average_result(&self) -> Option<f64> {
+        if self.games == 0 {
+            return None;
+        }
+        Some(self.total_result as f64 / self.games as f64)
+    }
+
+    // Most played openings with their game counts, ties in transcript order
+    pub fn top_openings(&self, count: usize) -> Vec<(String, usize)> {
+        let mut openings: Vec<(String, usize)> = self
+            .openings
+            .iter()
+            .map(|(opening, &games)| (opening.clone(), games))
+            .collect();
+        openings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        openings.truncate(count);
+        openings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_transcript;
+
+    fn record(transcript: &str, result: Option<i32>) -> GameRecord {
+        let moves = parse_transcript(transcript).unwrap();
+        GameRecord::new(
+            "black".to_string(),
+            "white".to_string(),
+            result,
+            GameState::new(),
+            moves,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_positions() {
+        let game = record("f5d6c5f4e3f6g5e6e7", None);
+        assert_eq!(game.positions().count(), 10);
+        assert_eq!(game.positions().next(), Some(GameState::new()));
+        assert!(game.final_state().is_terminal());
+        assert_eq!(game.transcript(), "f5d6c5f4e3f6g5e6e7");
+    }
+
+    #[test]
+    fn test_illegal_record() {
+        let moves = parse_transcript("f5d6").unwrap();
+        let result = GameRecord::new(
+            String::new(),
+            String::new(),
+            None,
+            GameState::new(),
+            moves.into_iter().rev(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::default();
+        summary.add(&record("f5d6c5f4e3f6g5e6e7", None), 2);
+        summary.add(&record("f5d6c3", Some(10)), 2);
+        summary.add(&record("f5f6", Some(-4)), 2);
+        summary.add_error();
+        assert_eq!(summary.games, 3);
+        assert_eq!(summary.errors, 1);
+        assert_eq!(summary.average_result(), Some((13.0 + 10.0 - 4.0) / 3.0));
+        assert_eq!(summary.top_openings(1), vec![("f5d6".to_string(), 2)]);
+    }
+}
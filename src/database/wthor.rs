@@ -0,0 +1,164 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use super::{invalid_data, GameRecord};
+use crate::bitboard_position;
+use crate::game::{Action, GameState};
+
+const HEADER_SIZE: usize = 16;
+const RECORD_SIZE: usize = 68;
+
+// Streams games out of a WTHOR (.wtb) database one 68 byte record at a time
+pub struct WthorReader<R: Read> {
+    reader: R,
+    remaining: u32,
+    year: u16,
+}
+
+impl WthorReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        WthorReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> WthorReader<R> {
+    // Reads the 16 byte header, only 8x8 game databases are supported
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let remaining = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let year = u16::from_le_bytes([header[10], header[11]]);
+        let board_size = header[12];
+        if board_size != 0 && board_size != 8 {
+            return Err(invalid_data(format!("unsupported board size {board_size}")));
+        }
+        Ok(WthorReader {
+            reader,
+            remaining,
+            year,
+        })
+    }
+
+    // Year the games in the file were played
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    // Games left to read according to the header
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+}
+
+// Squares are stored as 10 * row + column counting from 1, 0 ends the game
+fn parse_square(value: u8) -> Option<u64> {
+    let (row, column) = (value / 10, value % 10);
+    if !(1..=8).contains(&row) || !(1..=8).contains(&column) {
+        return None;
+    }
+    Some(bitboard_position(row - 1, column - 1))
+}
+
+fn parse_record(record: &[u8; RECORD_SIZE]) -> io::Result<GameRecord> {
+    let black = u16::from_le_bytes([record[2], record[3]]);
+    let white = u16::from_le_bytes([record[4], record[5]]);
+    let black_discs = record[6] as i32;
+    let mut moves = Vec::new();
+    for (index, &value) in record[8..].iter().enumerate() {
+        if value == 0 {
+            break;
+        }
+        let position = parse_square(value)
+            .ok_or_else(|| invalid_data(format!("move {}: {value} is not a square", index + 1)))?;
+        moves.push(Action::Place(position));
+    }
+    GameRecord::new(
+        format!("#{black}"),
+        format!("#{white}"),
+        Some(2 * black_discs - 64),
+        GameState::new(),
+        moves,
+    )
+}
+
+impl<R: Read> Iterator for WthorReader<R> {
+    type Item = io::Result<GameRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut record = [0; RECORD_SIZE];
+        if let Err(error) = self.reader.read_exact(&mut record) {
+            // Nothing sensible follows a truncated file
+            self.remaining = 0;
+            return Some(Err(error));
+        }
+        Some(parse_record(&record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::write_transcript;
+
+    fn database(games: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = vec![20, 24, 1, 1];
+        bytes.extend((games.len() as u32).to_le_bytes());
+        bytes.extend([0, 0]);
+        bytes.extend(2024u16.to_le_bytes());
+        bytes.extend([8, 0, 0, 0]);
+        for moves in games {
+            let mut record = [0; RECORD_SIZE];
+            record[2..4].copy_from_slice(&7u16.to_le_bytes());
+            record[4..6].copy_from_slice(&9u16.to_le_bytes());
+            record[6] = 64;
+            record[7] = 64;
+            record[8..8 + moves.len()].copy_from_slice(moves);
+            bytes.extend(record);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_read_games() {
+        // f5 d6 c5 f4 e3 f6 g5 e6 e7
+        let shortest: &[u8] = &[56, 64, 53, 46, 35, 66, 57, 65, 75];
+        let bytes = database(&[shortest, &[56, 66]]);
+        let mut reader = WthorReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.year(), 2024);
+        assert_eq!(reader.remaining(), 2);
+
+        let game = reader.next().unwrap().unwrap();
+        assert_eq!(write_transcript(&game.moves), "f5d6c5f4e3f6g5e6e7");
+        assert_eq!((game.black.as_str(), game.white.as_str()), ("#7", "#9"));
+        assert_eq!(game.result, Some(64));
+        assert!(game.final_state().is_terminal());
+
+        assert_eq!(reader.next().unwrap().unwrap().moves.len(), 2);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_invalid_games() {
+        // Illegal second move, then an off board square
+        let bytes = database(&[&[56, 56], &[56, 90]]);
+        let games: Vec<_> = WthorReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(games.len(), 2);
+        assert!(games.iter().all(|game| game.is_err()));
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let mut bytes = database(&[&[56]]);
+        bytes.truncate(HEADER_SIZE + 10);
+        let mut reader = WthorReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,348 @@
+use crate::bitboard_position;
+use crate::eval::{Evaluator, PatternEvaluator, Weights};
+use crate::game::{Action, GameState, Side};
+use crate::notation::{parse_square, square_name};
+use crate::search::{search_with_evaluator, SearchLimits};
+use crate::transposition::TranspositionTable;
+
+// Line protocol for plugging engines together, modelled on GTP: every
+// command is one line, optionally preceded by a numeric id, and is answered
+// with "=id result" or "?id error" followed by a blank line
+
+const NAME: &str = "reversi";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const COMMANDS: &[&str] = &[
+    "protocol_version",
+    "name",
+    "version",
+    "known_command",
+    "list_commands",
+    "boardsize",
+    "clear_board",
+    "komi",
+    "play",
+    "genmove",
+    "undo",
+    "showboard",
+    "final_score",
+    "quit",
+];
+
+pub fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Black => "black",
+        Side::White => "white",
+    }
+}
+
+pub fn parse_side(text: &str) -> Option<Side> {
+    match text.to_ascii_lowercase().as_str() {
+        "b" | "black" => Some(Side::Black),
+        "w" | "white" => Some(Side::White),
+        _ => None,
+    }
+}
+
+// Squares are named like in transcripts, passes are spelled out
+pub fn vertex_name(action: Action) -> String {
+    match action {
+        Action::Place(position) => square_name(position),
+        Action::Pass => "pass".to_string(),
+    }
+}
+
+pub fn parse_vertex(text: &str) -> Option<Action> {
+    if text.eq_ignore_ascii_case("pass") {
+        return Some(Action::Pass);
+    }
+    parse_square(text).map(Action::Place)
+}
+
+// Board with X for black, O for white and the side to move underneath
+pub fn board_diagram(state: &GameState) -> String {
+    let config = state.config;
+    let mut diagram = String::from("  a b c d e f g h");
+    for row in 0..8 {
+        diagram.push_str(&format!("\n{}", row + 1));
+        for column in 0..8 {
+            let position = bitboard_position(row, column);
+            let disc = if config.black_pieces & position != 0 {
+                'X'
+            } else if config.white_pieces & position != 0 {
+                'O'
+            } else {
+                '.'
+            };
+            diagram.push(' ');
+            diagram.push(disc);
+        }
+    }
+    diagram.push_str(&format!("\n{} to move", side_name(state.side_to_move())));
+    diagram
+}
+
+// Engine side of the protocol, playing with the library's rules and search
+pub struct Engine {
+    state: GameState,
+    // Positions before every move, for undo
+    history: Vec<GameState>,
+    limits: SearchLimits,
+    table: TranspositionTable,
+    evaluator: Box<dyn Evaluator>,
+    quit: bool,
+}
+
+impl Engine {
+    pub fn new(limits: SearchLimits) -> Engine {
+        Engine {
+            state: GameState::new(),
+            history: Vec::new(),
+            limits,
+            table: TranspositionTable::default(),
+            evaluator: Box::new(PatternEvaluator::default()),
+            quit: false,
+        }
+    }
+
+    // Sizes the transposition table kept between moves to the given bytes
+    pub fn with_memory(mut self, bytes: usize) -> Engine {
+        self.table = TranspositionTable::with_memory(bytes);
+        self
+    }
+
+    pub fn with_weights(mut self, weights: Weights) -> Engine {
+        self.evaluator = Box::new(PatternEvaluator::new(weights));
+        self
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    // Set once quit was received
+    pub fn is_done(&self) -> bool {
+        self.quit
+    }
+
+    // Plays an action for the given side, a side that has no move passes
+    // first so controllers do not have to send every pass
+    fn play(&mut self, side: Side, action: Action) -> Result<(), String> {
+        let mut state = self.state;
+        if side != state.side_to_move() && state.must_pass() {
+            state = state.apply(Action::Pass).unwrap();
+        }
+        if side != state.side_to_move() {
+            return Err("illegal move".to_string());
+        }
+        let next = state.apply(action).ok_or("illegal move")?;
+        self.history.push(self.state);
+        self.state = next;
+        Ok(())
+    }
+
+    fn genmove(&mut self, side: Side) -> Result<String, String> {
+        let mut state = self.state;
+        if side != state.side_to_move() && state.must_pass() {
+            state = state.apply(Action::Pass).unwrap();
+        }
+        if side != state.side_to_move() {
+            return Err(format!("{} is not to move", side_name(side)));
+        }
+        if state.is_terminal() {
+            return Ok("pass".to_string());
+        }
+        let result = search_with_evaluator(&state, &self.limits, &mut self.table, &*self.evaluator);
+        let action = result.best.unwrap_or(Action::Pass);
+        self.play(side, action)?;
+        Ok(vertex_name(action))
+    }
+
+    fn final_score(&self) -> String {
+        let (black, white) = self.state.score();
+        match black.cmp(&white) {
+            std::cmp::Ordering::Greater => format!("B+{}", black - white),
+            std::cmp::Ordering::Less => format!("W+{}", white - black),
+            std::cmp::Ordering::Equal => "0".to_string(),
+        }
+    }
+
+    // Runs a single command with its arguments
+    pub fn execute(&mut self, command: &str, arguments: &[&str]) -> Result<String, String> {
+        let side = |index: usize| {
+            arguments
+                .get(index)
+                .and_then(|text| parse_side(text))
+                .ok_or_else(|| "invalid color".to_string())
+        };
+        match command {
+            "protocol_version" => Ok("2".to_string()),
+            "name" => Ok(NAME.to_string()),
+            "version" => Ok(VERSION.to_string()),
+            "known_command" => {
+                let known = arguments
+                    .first()
+                    .is_some_and(|name| COMMANDS.contains(name));
+                Ok(known.to_string())
+            }
+            "list_commands" => Ok(COMMANDS.join("\n")),
+            "boardsize" => match arguments.first().map(|size| size.parse()) {
+                Some(Ok(8)) => Ok(String::new()),
+                Some(Ok(_)) => Err("unacceptable size".to_string()),
+                _ => Err("invalid size".to_string()),
+            },
+            "clear_board" => {
+                self.state = GameState::new();
+                self.history.clear();
+                Ok(String::new())
+            }
+            // There is no komi in reversi, accepted so controllers made for
+            // go do not trip over it
+            "komi" => Ok(String::new()),
+            "play" => {
+                let side = side(0)?;
+                let action = arguments
+                    .get(1)
+                    .and_then(|text| parse_vertex(text))
+                    .ok_or("invalid vertex")?;
+                self.play(side, action).map(|_| String::new())
+            }
+            "genmove" => self.genmove(side(0)?),
+            "undo" => {
+                self.state = self.history.pop().ok_or("cannot undo")?;
+                Ok(String::new())
+            }
+            "showboard" => Ok(format!("\n{}", board_diagram(&self.state))),
+            "final_score" => Ok(self.final_score()),
+            "quit" => {
+                self.quit = true;
+                Ok(String::new())
+            }
+            _ => Err("unknown command".to_string()),
+        }
+    }
+
+    // Answers one line of input, None for blank lines and comments
+    pub fn respond(&mut self, line: &str) -> Option<String> {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let mut command = words.next()?;
+        let mut id = "";
+        if command.bytes().all(|byte| byte.is_ascii_digit()) {
+            id = command;
+            command = words.next()?;
+        }
+        let arguments: Vec<&str> = words.collect();
+        let response = match self.execute(&command.to_ascii_lowercase(), &arguments) {
+            Ok(result) => format!("={id} {result}"),
+            Err(error) => format!("?{id} {error}"),
+        };
+        Some(format!("{}\n\n", response.trim_end()))
+    }
+}
+
+// Splits a response into its result or error, the id is dropped
+pub fn parse_response(response: &str) -> Result<String, String> {
+    let (status, rest) = response.split_at(response.len().min(1));
+    let result = rest
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim()
+        .to_string();
+    match status {
+        "=" => Ok(result),
+        "?" => Err(result),
+        _ => Err(format!("malformed response {response:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> Engine {
+        Engine::new(SearchLimits::depth(2))
+    }
+
+    #[test]
+    fn test_responses() {
+        let mut engine = engine();
+        assert_eq!(engine.respond("protocol_version"), Some("= 2\n\n".into()));
+        assert_eq!(engine.respond("7 boardsize 8"), Some("=7\n\n".into()));
+        assert_eq!(
+            engine.respond("boardsize 19"),
+            Some("? unacceptable size\n\n".into())
+        );
+        assert_eq!(
+            engine.respond("frobnicate"),
+            Some("? unknown command\n\n".into())
+        );
+        assert_eq!(
+            engine.respond("known_command genmove"),
+            Some("= true\n\n".into())
+        );
+        assert_eq!(engine.respond("   # comment"), None);
+        assert_eq!(engine.respond(""), None);
+        engine.respond("quit");
+        assert!(engine.is_done());
+    }
+
+    #[test]
+    fn test_play_and_undo() {
+        let mut engine = engine();
+        assert_eq!(engine.execute("play", &["black", "f5"]), Ok(String::new()));
+        assert!(engine.execute("play", &["black", "d6"]).is_err());
+        assert!(engine.execute("play", &["white", "a1"]).is_err());
+        assert!(engine.execute("play", &["purple", "d6"]).is_err());
+        assert_eq!(engine.state().side_to_move(), Side::White);
+        assert_eq!(engine.execute("undo", &[]), Ok(String::new()));
+        assert_eq!(*engine.state(), GameState::new());
+        assert!(engine.execute("undo", &[]).is_err());
+    }
+
+    #[test]
+    fn test_genmove() {
+        let mut engine = engine();
+        assert!(engine.execute("genmove", &["white"]).is_err());
+        let vertex = engine.execute("genmove", &["black"]).unwrap();
+        let Some(Action::Place(position)) = parse_vertex(&vertex) else {
+            panic!("{vertex} is not a square");
+        };
+        assert!(GameState::new().move_mask() & position != 0);
+        assert_eq!(engine.state().side_to_move(), Side::White);
+    }
+
+    #[test]
+    fn test_final_score() {
+        let mut engine = engine();
+        for (side, square) in ["f5", "d6", "c5", "f4", "e3", "f6", "g5", "e6", "e7"]
+            .iter()
+            .enumerate()
+            .map(|(ply, square)| (["b", "w"][ply % 2], square))
+        {
+            assert_eq!(engine.execute("play", &[side, square]), Ok(String::new()));
+        }
+        assert!(engine.state().is_terminal());
+        assert_eq!(engine.execute("final_score", &[]), Ok("B+13".to_string()));
+        assert_eq!(engine.execute("genmove", &["w"]), Ok("pass".to_string()));
+    }
+
+    #[test]
+    fn test_showboard() {
+        let board = engine().execute("showboard", &[]).unwrap();
+        let lines: Vec<&str> = board.lines().collect();
+        assert_eq!(lines[1], "  a b c d e f g h");
+        assert_eq!(lines[5], "4 . . . O X . . .");
+        assert_eq!(lines[10], "black to move");
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(parse_response("= f5"), Ok("f5".to_string()));
+        assert_eq!(parse_response("=12 pass"), Ok("pass".to_string()));
+        assert_eq!(
+            parse_response("? illegal move"),
+            Err("illegal move".to_string())
+        );
+        assert!(parse_response("f5").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    all_placable, legal_moves, stable_discs, BOTTOM_EDGE, LEFT_BOTTOM_MESH, LEFT_EDGE,
+    LEFT_TOP_MESH, RIGHT_BOTTOM_MESH, RIGHT_EDGE, RIGHT_TOP_MESH, TOP_EDGE,
+};
+
+const EDGES: u64 = TOP_EDGE | BOTTOM_EDGE | LEFT_EDGE | RIGHT_EDGE;
+
+// Each corner with the squares around it, the X-square is the diagonal
+// neighbour and the C-squares are the neighbours along the edges
+const CORNER_MESHES: [(u64, u64); 4] = [
+    (TOP_EDGE & LEFT_EDGE, LEFT_TOP_MESH),
+    (TOP_EDGE & RIGHT_EDGE, RIGHT_TOP_MESH),
+    (BOTTOM_EDGE & LEFT_EDGE, LEFT_BOTTOM_MESH),
+    (BOTTOM_EDGE & RIGHT_EDGE, RIGHT_BOTTOM_MESH),
+];
+
+// Scores positions for the side to move, positive when ally is ahead
+pub trait Evaluator {
+    fn evaluate(&self, ally: u64, foe: u64) -> i32;
+}
+
+// Weight applied to the ally minus foe difference of each feature
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    pub mobility: i32,
+    pub potential_mobility: i32,
+    pub corner: i32,
+    pub x_square: i32,
+    pub c_square: i32,
+    pub stability: i32,
+    pub parity: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            mobility: 10,
+            potential_mobility: 3,
+            corner: 100,
+            x_square: -40,
+            c_square: -15,
+            stability: 20,
+            parity: 10,
+        }
+    }
+}
+
+impl Weights {
+    // Parses "name = value" lines, blank lines and lines starting with # are
+    // skipped and features left out keep their default weight
+    pub fn parse(text: &str) -> io::Result<Weights> {
+        let mut weights = Weights::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {message}", number + 1),
+                )
+            };
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid("expected name = value"))?;
+            let value: i32 = value
+                .trim()
+                .parse()
+                .map_err(|_| invalid("weight is not an integer"))?;
+            let weight = match name.trim() {
+                "mobility" => &mut weights.mobility,
+                "potential_mobility" => &mut weights.potential_mobility,
+                "corner" => &mut weights.corner,
+                "x_square" => &mut weights.x_square,
+                "c_square" => &mut weights.c_square,
+                "stability" => &mut weights.stability,
+                "parity" => &mut weights.parity,
+                _ => return Err(invalid("unknown feature")),
+            };
+            *weight = value;
+        }
+        Ok(weights)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Weights> {
+        Weights::parse(&fs::read_to_string(path)?)
+    }
+}
+
+// Mobility, frontier, corner region, stability and parity features
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PatternEvaluator {
+    pub weights: Weights,
+}
+
+impl PatternEvaluator {
+    pub fn new(weights: Weights) -> PatternEvaluator {
+        PatternEvaluator { weights }
+    }
+}
+
+impl Evaluator for PatternEvaluator {
+    fn evaluate(&self, ally: u64, foe: u64) -> i32 {
+        let weights = &self.weights;
+        let empty = !(ally | foe);
+        let count = |board: u64| board.count_ones() as i32;
+
+        let mobility = count(legal_moves(ally, foe)) - count(legal_moves(foe, ally));
+        // Empty squares next to the opponent are where moves may open up later
+        let potential_mobility = count(all_placable(foe).unwrap_or(0) & empty)
+            - count(all_placable(ally).unwrap_or(0) & empty);
+
+        let mut corner = 0;
+        let mut x_square = 0;
+        let mut c_square = 0;
+        for (corner_square, mesh) in CORNER_MESHES {
+            corner += count(ally & corner_square) - count(foe & corner_square);
+            // Squares next to a taken corner are no longer a liability
+            if corner_square & empty != 0 {
+                let x = mesh & !EDGES;
+                let c = mesh & EDGES;
+                x_square += count(ally & x) - count(foe & x);
+                c_square += count(ally & c) - count(foe & c);
+            }
+        }
+
+        let stability = count(stable_discs(ally, foe)) - count(stable_discs(foe, ally));
+        // The side to move gets the last move when an odd number of squares is left
+        let parity = if count(empty) % 2 == 1 { 1 } else { -1 };
+
+        weights.mobility * mobility
+            + weights.potential_mobility * potential_mobility
+            + weights.corner * corner
+            + weights.x_square * x_square
+            + weights.c_square * c_square
+            + weights.stability * stability
+            + weights.parity * parity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard_position;
+    use crate::game::GameState;
+
+    #[test]
+    fn test_parse_weights() {
+        let weights = Weights::parse("# tuned\nmobility = 4\n\n  corner=250\n").unwrap();
+        assert_eq!(weights.mobility, 4);
+        assert_eq!(weights.corner, 250);
+        assert_eq!(weights.parity, Weights::default().parity);
+
+        assert!(Weights::parse("mobility 4").is_err());
+        assert!(Weights::parse("mobility = four").is_err());
+        assert!(Weights::parse("edges = 3").is_err());
+    }
+
+    #[test]
+    fn test_symmetric_opening() {
+        let (ally, foe) = GameState::new().config.ally_foe();
+        let evaluator = PatternEvaluator::default();
+        assert_eq!(evaluator.evaluate(ally, foe), evaluator.evaluate(foe, ally));
+    }
+
+    #[test]
+    fn test_corner_beats_x_square() {
+        let evaluator = PatternEvaluator::new(Weights {
+            mobility: 0,
+            potential_mobility: 0,
+            parity: 0,
+            ..Weights::default()
+        });
+        let foe = bitboard_position(4, 4);
+        let corner = evaluator.evaluate(bitboard_position(0, 0), foe);
+        let x_square = evaluator.evaluate(bitboard_position(1, 1), foe);
+        assert!(corner > 0);
+        assert!(x_square < 0);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::bitboard::Bitboard;
+use crate::{flips, legal_moves, neighbours};
+
+// White on d4 and e5, black on d5 and e4 with a1 in the top left corner
+pub const START_WHITE: u64 = 68853694464;
+pub const START_BLACK: u64 = 34628173824;
+pub const CENTRE: u64 = START_WHITE | START_BLACK;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PieceConfig {
+    pub white_pieces: u64,
+    pub black_pieces: u64,
+    pub blacks_play: bool,
+}
+
+impl PieceConfig {
+    pub fn ally_foe(&self) -> (u64, u64) {
+        if self.blacks_play {
+            return (self.black_pieces, self.white_pieces);
+        }
+        (self.white_pieces, self.black_pieces)
+    }
+
+    // Reasons the position cannot come up in a game, empty if it can. The
+    // centre is never emptied and every disc is placed next to another, so
+    // all discs hang together with the centre
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let occupied = self.black_pieces | self.white_pieces;
+        let both = self.black_pieces & self.white_pieces;
+        if both != 0 {
+            problems.push(format!("both colours on {}", squares(both)));
+        }
+        let empty = CENTRE & !occupied;
+        if empty != 0 {
+            problems.push(format!("empty centre squares {}", squares(empty)));
+        }
+        let mut connected = CENTRE & occupied;
+        loop {
+            let grown = connected | (neighbours(connected) & occupied);
+            if grown == connected {
+                break;
+            }
+            connected = grown;
+        }
+        let loose = occupied & !connected;
+        if loose != 0 {
+            problems.push(format!("cut off from the centre {}", squares(loose)));
+        }
+        problems
+    }
+}
+
+fn squares(board: u64) -> String {
+    let names: Vec<String> = Bitboard(board)
+        .iter()
+        .map(|square| square.to_string())
+        .collect();
+    names.join(" ")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Black,
+    White,
+}
+
+impl Side {
+    pub fn opponent(self) -> Side {
+        match self {
+            Side::Black => Side::White,
+            Side::White => Side::Black,
+        }
+    }
+}
+
+// A single turn, passing is only legal when no placement is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Place(u64),
+    Pass,
+}
+
+// Board position together with the side to move, independent of any renderer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GameState {
+    pub config: PieceConfig,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        GameState::new()
+    }
+}
+
+impl From<PieceConfig> for GameState {
+    fn from(config: PieceConfig) -> Self {
+        GameState { config }
+    }
+}
+
+impl GameState {
+    // Standard opening position with black to move
+    pub fn new() -> GameState {
+        GameState {
+            config: PieceConfig {
+                white_pieces: START_WHITE,
+                black_pieces: START_BLACK,
+                blacks_play: true,
+            },
+        }
+    }
+
+    pub fn side_to_move(&self) -> Side {
+        if self.config.blacks_play {
+            Side::Black
+        } else {
+            Side::White
+        }
+    }
+
+    // Mesh of squares the side to move can place on
+    pub fn move_mask(&self) -> u64 {
+        let (ally, foe) = self.config.ally_foe();
+        legal_moves(ally, foe)
+    }
+
+    // Every legal action, a lone Pass when stuck and empty once the game is over
+    pub fn legal_moves(&self) -> Vec<Action> {
+        let mut moves = self.move_mask();
+        if moves == 0 {
+            if self.is_terminal() {
+                return Vec::new();
+            }
+            return vec![Action::Pass];
+        }
+        let mut actions = Vec::with_capacity(moves.count_ones() as usize);
+        while moves != 0 {
+            actions.push(Action::Place(moves & moves.wrapping_neg()));
+            moves &= moves - 1;
+        }
+        actions
+    }
+
+    // Side to move has no placement but the game is not over yet
+    pub fn must_pass(&self) -> bool {
+        self.move_mask() == 0 && !self.is_terminal()
+    }
+
+    // Resulting state after the action, None if it is not legal here
+    pub fn apply(&self, action: Action) -> Option<GameState> {
+        let (ally, foe) = self.config.ally_foe();
+        let (ally, foe) = match action {
+            Action::Pass => {
+                if !self.must_pass() {
+                    return None;
+                }
+                (ally, foe)
+            }
+            Action::Place(position) => {
+                if !position.is_power_of_two() || legal_moves(ally, foe) & position == 0 {
+                    return None;
+                }
+                let flipped = flips(ally, foe, position);
+                (ally | flipped | position, foe & !flipped)
+            }
+        };
+        let (black_pieces, white_pieces) = if self.config.blacks_play {
+            (ally, foe)
+        } else {
+            (foe, ally)
+        };
+        Some(GameState {
+            config: PieceConfig {
+                white_pieces,
+                black_pieces,
+                blacks_play: !self.config.blacks_play,
+            },
+        })
+    }
+
+    // Neither side has a legal placement left
+    pub fn is_terminal(&self) -> bool {
+        let (ally, foe) = self.config.ally_foe();
+        legal_moves(ally, foe) == 0 && legal_moves(foe, ally) == 0
+    }
+
+    // Piece count as (black, white)
+    pub fn score(&self) -> (u32, u32) {
+        (
+            self.config.black_pieces.count_ones(),
+            self.config.white_pieces.count_ones(),
+        )
+    }
+
+    // Side with more pieces once the game is over, None while playing or on a draw
+    pub fn winner(&self) -> Option<Side> {
+        if !self.is_terminal() {
+            return None;
+        }
+        let (black, white) = self.score();
+        match black.cmp(&white) {
+            std::cmp::Ordering::Greater => Some(Side::Black),
+            std::cmp::Ordering::Less => Some(Side::White),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard_position;
+
+    #[test]
+    fn test_opening_moves() {
+        let state = GameState::new();
+        assert_eq!(state.side_to_move(), Side::Black);
+        assert_eq!(state.legal_moves().len(), 4);
+        assert_eq!(state.move_mask(), 17729692631040);
+        assert!(state.apply(Action::Pass).is_none());
+        assert!(state.apply(Action::Place(1)).is_none());
+    }
+
+    #[test]
+    fn test_apply_flips() {
+        let state = GameState::new();
+        let position = bitboard_position(2, 3);
+        let next = state.apply(Action::Place(position)).unwrap();
+        assert_eq!(next.side_to_move(), Side::White);
+        assert_eq!(next.score(), (4, 1));
+        assert!(next.config.black_pieces & position != 0);
+    }
+
+    #[test]
+    fn test_forced_pass() {
+        // White to move with no placement, black still has one
+        let state = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0),
+            white_pieces: bitboard_position(0, 1),
+            blacks_play: false,
+        });
+        assert!(state.must_pass());
+        assert_eq!(state.legal_moves(), vec![Action::Pass]);
+        assert!(state
+            .apply(Action::Place(bitboard_position(0, 2)))
+            .is_none());
+        let next = state.apply(Action::Pass).unwrap();
+        assert_eq!(next.side_to_move(), Side::Black);
+        assert_eq!(next.move_mask(), bitboard_position(0, 2));
+        assert!(next.apply(Action::Pass).is_none());
+    }
+
+    #[test]
+    fn test_game_over() {
+        let state = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0) | bitboard_position(0, 1),
+            white_pieces: bitboard_position(7, 7),
+            blacks_play: true,
+        });
+        assert!(state.is_terminal());
+        assert!(state.legal_moves().is_empty());
+        assert!(state.apply(Action::Pass).is_none());
+        assert_eq!(state.score(), (2, 1));
+        assert_eq!(state.winner(), Some(Side::Black));
+        assert_eq!(GameState::new().winner(), None);
+    }
+
+    #[test]
+    fn test_problems() {
+        assert!(GameState::new().config.problems().is_empty());
+        let config = PieceConfig {
+            white_pieces: START_WHITE | bitboard_position(0, 0),
+            black_pieces: START_BLACK & !bitboard_position(3, 4) | bitboard_position(4, 4),
+            blacks_play: true,
+        };
+        assert_eq!(
+            config.problems(),
+            [
+                "both colours on e5",
+                "empty centre squares e4",
+                "cut off from the centre a1"
+            ]
+        );
+    }
+}
@@ -2,37 +2,17 @@ use std::collections::HashMap;
 
 use ggez::glam::Vec2;
 use ggez::graphics::{Color, FillOptions, MeshBuilder};
-use ggez::input::keyboard::KeyInput;
+use ggez::input::keyboard::{KeyCode, KeyInput};
 use ggez::{event, graphics};
 use ggez::{event::EventHandler, GameError, GameResult};
 
-use crate::{bitboard_position, bitboard_rowcol, piece_positions};
+use crate::bitboard::Bitboard;
+use crate::bitboard_position;
+use crate::book::Book;
+use crate::controller::{CaptureHandler, Controller, Event, Input, Player, ValidHandler};
+use crate::notation::square_name;
 
-pub enum Move {
-    Position(u64),
-    Board,
-}
-
-pub trait Player {
-    fn play_move(&mut self, config: &PieceConfig) -> Move;
-    fn enemy_move(&mut self, current_move: u64);
-}
-
-#[derive(Clone, PartialEq)]
-pub struct PieceConfig {
-    pub white_pieces: u64,
-    pub black_pieces: u64,
-    pub blacks_play: bool,
-}
-
-impl PieceConfig {
-    pub fn ally_foe(&self) -> (u64, u64) {
-        if self.blacks_play {
-            return (self.black_pieces, self.white_pieces);
-        }
-        return (self.white_pieces, self.black_pieces);
-    }
-}
+pub use crate::game::PieceConfig;
 
 #[derive(Clone)]
 pub struct BoardConfig {
@@ -49,23 +29,33 @@ impl BoardConfig {
     }
 }
 
+pub type KeypressHandler = Box<dyn Fn(KeyInput, &BoardConfig) -> BoardConfig>;
+// Lines of text shown next to the board, such as the clocks
+pub type StatusHandler = Box<dyn Fn(&PieceConfig) -> Vec<String>>;
+// Position after clicking a square with a mouse button, None when the click
+// is not an edit and is left to the players
+pub type EditHandler = Box<dyn Fn(&PieceConfig, u64, event::MouseButton) -> Option<PieceConfig>>;
+
 pub struct Board {
     square_size: f32,
     pub config: BoardConfig,
-    handle_keypress: Box<dyn Fn(KeyInput, &BoardConfig) -> BoardConfig>,
-    capture: Box<dyn Fn(&PieceConfig, u64) -> PieceConfig>,
-    valid: Box<dyn Fn(&PieceConfig, u64) -> bool>,
-    black: Box<dyn Player>,
-    white: Box<dyn Player>,
+    handle_keypress: KeypressHandler,
+    // Players, position and history, the board only draws them and turns
+    // window events into steps
+    controller: Controller,
+    status: Option<StatusHandler>,
+    edit: Option<EditHandler>,
+    book: Option<Book>,
+    move_list: bool,
 }
 
 impl Board {
     pub fn new(
         square_size: f32,
         piece_config: PieceConfig,
-        handle_keypress: Box<dyn Fn(KeyInput, &BoardConfig) -> BoardConfig>,
-        capture: Box<dyn Fn(&PieceConfig, u64) -> PieceConfig>,
-        valid: Box<dyn Fn(&PieceConfig, u64) -> bool>,
+        handle_keypress: KeypressHandler,
+        capture: CaptureHandler,
+        valid: ValidHandler,
         black: Box<dyn Player>,
         white: Box<dyn Player>,
     ) -> Board {
@@ -73,13 +63,125 @@ impl Board {
             square_size,
             config: BoardConfig::new(piece_config),
             handle_keypress,
-            capture,
-            valid,
-            black,
-            white,
+            controller: Controller::new(piece_config, capture, valid, black, white),
+            status: None,
+            edit: None,
+            book: None,
+            move_list: false,
+        }
+    }
+
+    // Shows the moves next to the board, arrow keys step through them and
+    // clicking one jumps to its position. Players are not told, so this is
+    // meant for boards whose players keep no game of their own
+    pub fn with_move_list(mut self) -> Board {
+        self.move_list = true;
+        self
+    }
+
+    // Moves played to reach the position on the board
+    pub fn moves(&self) -> &[u64] {
+        &self.controller.moves()[..self.controller.cursor()]
+    }
+
+    // Shows the position after the given number of moves of the history
+    pub fn jump(&mut self, index: usize) {
+        self.step(Input::Jump(index));
+    }
+
+    pub fn undo(&mut self) {
+        self.step(Input::Undo);
+    }
+
+    pub fn redo(&mut self) {
+        self.step(Input::Redo);
+    }
+
+    // Keeps the drawn position in line with the controller, positions not
+    // reached by a move drop the overlays
+    fn step(&mut self, input: Input) {
+        let events = self.controller.step(input);
+        if events
+            .iter()
+            .any(|event| matches!(event, Event::Reset | Event::Jumped(_)))
+        {
+            self.config = BoardConfig::new(*self.controller.config());
+        } else {
+            self.config.piece_config = *self.controller.config();
         }
     }
 
+    fn margin(&self) -> f32 {
+        self.square_size / 4.0
+    }
+
+    fn line_height(&self) -> f32 {
+        self.square_size / 4.0
+    }
+
+    // Rows of text next to the board, moves come with the history index
+    // clicking them jumps to
+    fn panel(&self) -> Vec<(String, Option<usize>)> {
+        let mut rows: Vec<(String, Option<usize>)> = match &self.status {
+            Some(status) => status(&self.config.piece_config)
+                .into_iter()
+                .map(|line| (line, None))
+                .collect(),
+            None => Vec::new(),
+        };
+        let positions = self.controller.positions();
+        let cursor = self.controller.cursor();
+        if let Some(book) = &self.book {
+            if let Some(name) = book.opening(&positions[..=cursor]) {
+                rows.push((name.to_string(), None));
+            }
+        }
+        if !self.move_list {
+            return rows;
+        }
+        if !rows.is_empty() {
+            rows.push((String::new(), None));
+        }
+        // Only as many moves as fit, keeping the current one in view
+        let fit = ((self.square_size * 8.0 - self.margin()) / self.line_height()) as usize;
+        let visible = fit.saturating_sub(rows.len()).max(1);
+        let first = (cursor + 1).saturating_sub(visible);
+        for index in first..positions.len().min(first + visible) {
+            let row = match index {
+                0 => "start".to_string(),
+                _ => {
+                    let side = match positions[index - 1].blacks_play {
+                        true => "black",
+                        false => "white",
+                    };
+                    let position = self.controller.moves()[index - 1];
+                    format!("{index:>2}. {side} {}", square_name(position))
+                }
+            };
+            rows.push((row, Some(index)));
+        }
+        rows
+    }
+
+    // Draws the handler's lines to the right of the board, the window has to
+    // be wide enough to show them
+    pub fn with_status(mut self, status: StatusHandler) -> Board {
+        self.status = Some(status);
+        self
+    }
+
+    // Shows the name of the opening played so far
+    pub fn with_book(mut self, book: Book) -> Board {
+        self.book = Some(book);
+        self
+    }
+
+    // Lets any mouse button edit the position, edits start a new history
+    pub fn with_editor(mut self, edit: EditHandler) -> Board {
+        self.edit = Some(edit);
+        self
+    }
+
     fn colored_mesh(
         &mut self,
         ctx: &mut ggez::Context,
@@ -87,20 +189,17 @@ impl Board {
         color: Color,
     ) -> GameResult<graphics::Mesh> {
         let mesh_builder = &mut MeshBuilder::new();
-        if let Some(positions) = piece_positions(pieces) {
-            for position in positions {
-                let (row, column) = bitboard_rowcol(position);
-                mesh_builder.rectangle(
-                    graphics::DrawMode::Fill(FillOptions::default()),
-                    graphics::Rect::new(
-                        column as f32 * self.square_size,
-                        row as f32 * self.square_size,
-                        self.square_size,
-                        self.square_size,
-                    ),
-                    color,
-                )?;
-            }
+        for square in Bitboard(pieces) {
+            mesh_builder.rectangle(
+                graphics::DrawMode::Fill(FillOptions::default()),
+                graphics::Rect::new(
+                    square.file() as f32 * self.square_size,
+                    square.rank() as f32 * self.square_size,
+                    self.square_size,
+                    self.square_size,
+                ),
+                color,
+            )?;
         }
 
         Ok(graphics::Mesh::from_data(ctx, mesh_builder.build()))
@@ -132,38 +231,19 @@ impl Board {
 
 impl EventHandler<GameError> for Board {
     fn update(&mut self, ctx: &mut ggez::Context) -> Result<(), GameError> {
-        let played_move = if self.config.piece_config.blacks_play {
-            self.black.play_move(&self.config.piece_config)
-        } else {
-            self.white.play_move(&self.config.piece_config)
-        };
-
-        let position = match played_move {
-            Move::Position(position) => position,
-            Move::Board => {
-                if !ctx.mouse.button_pressed(event::MouseButton::Left) {
-                    return Ok(());
-                }
-                let position = ctx.mouse.position();
-                let row = (position.y / self.square_size) as u8;
-                let column = (position.x / self.square_size) as u8;
-                let position = bitboard_position(row, column);
-                if (self.valid)(&self.config.piece_config, position) {
-                    position
-                } else {
-                    return Ok(());
-                }
-            }
-        };
-
-        if self.config.piece_config.blacks_play {
-            self.white.enemy_move(position);
+        // Squares under the pressed button are offered to players leaving
+        // their move to the board
+        let position = ctx.mouse.position();
+        let (row, column) = (
+            (position.y / self.square_size) as u8,
+            (position.x / self.square_size) as u8,
+        );
+        let input = if ctx.mouse.button_pressed(event::MouseButton::Left) && row < 8 && column < 8 {
+            Input::Select(bitboard_position(row, column))
         } else {
-            self.black.enemy_move(position);
+            Input::Poll
         };
-
-        self.config.piece_config = (self.capture)(&self.config.piece_config, position);
-
+        self.step(input);
         Ok(())
     }
 
@@ -173,8 +253,61 @@ impl EventHandler<GameError> for Board {
         input: KeyInput,
         repeat: bool,
     ) -> GameResult {
+        if self.move_list {
+            let latest = self.controller.positions().len() - 1;
+            let target = match input.keycode {
+                Some(KeyCode::Left) => Some(Input::Undo),
+                Some(KeyCode::Right) => Some(Input::Redo),
+                Some(KeyCode::Home) => Some(Input::Jump(0)),
+                Some(KeyCode::End) => Some(Input::Jump(latest)),
+                _ => None,
+            };
+            if let Some(input) = target {
+                self.step(input);
+                return Ok(());
+            }
+        }
         if !repeat {
+            let previous = self.config.piece_config;
             self.config = (self.handle_keypress)(input, &self.config);
+            if self.config.piece_config != previous {
+                // Overlays set by the handler stay
+                self.controller
+                    .step(Input::SetPosition(self.config.piece_config));
+            }
+        }
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut ggez::Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        if x < self.square_size * 8.0 {
+            let (row, column) = ((y / self.square_size) as u8, (x / self.square_size) as u8);
+            let Some(edit) = &self.edit else {
+                return Ok(());
+            };
+            if row >= 8 || column >= 8 {
+                return Ok(());
+            }
+            let position = bitboard_position(row, column);
+            if let Some(piece_config) = edit(&self.config.piece_config, position, button) {
+                self.step(Input::SetPosition(piece_config));
+            }
+            return Ok(());
+        }
+        if !self.move_list || button != event::MouseButton::Left {
+            return Ok(());
+        }
+        let row = ((y - self.margin()) / self.line_height()).floor();
+        if row >= 0.0 {
+            if let Some((_, Some(index))) = self.panel().get(row as usize) {
+                self.jump(*index);
+            }
         }
         Ok(())
     }
@@ -199,7 +332,123 @@ impl EventHandler<GameError> for Board {
         }
 
         canvas.draw(&self.grid(ctx)?, graphics::DrawParam::new());
+        for (row, (line, index)) in self.panel().into_iter().enumerate() {
+            let mut text = graphics::Text::new(line);
+            text.set_scale(self.square_size / 5.0);
+            let x = self.square_size * 8.0 + self.margin();
+            let y = self.margin() + row as f32 * self.line_height();
+            let color = match index {
+                Some(index) if index == self.controller.cursor() => Color::WHITE,
+                _ => Color::BLACK,
+            };
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(Vec2::new(x, y))
+                    .color(color),
+            );
+        }
         canvas.finish(ctx)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::Move;
+    use crate::game::{Action, GameState};
+    use crate::notation::parse_square;
+
+    // Leaves every move to clicks on the board
+    struct Clicks {}
+
+    impl Player for Clicks {
+        fn play_move(&mut self, _config: &PieceConfig) -> Move {
+            Move::Board
+        }
+
+        fn enemy_move(&mut self, _current_move: u64) {}
+    }
+
+    fn board() -> Board {
+        Board::new(
+            100.0,
+            GameState::new().config,
+            Box::new(|_, config| config.clone()),
+            Box::new(|config, position| {
+                GameState::from(*config)
+                    .apply(Action::Place(position))
+                    .unwrap()
+                    .config
+            }),
+            Box::new(|_, _| true),
+            Box::new(Clicks {}),
+            Box::new(Clicks {}),
+        )
+        .with_move_list()
+    }
+
+    fn play(board: &mut Board, square: &str) {
+        let position = parse_square(square).unwrap();
+        board.step(Input::Select(position));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut board = board();
+        play(&mut board, "f5");
+        play(&mut board, "d6");
+        let after = board.config.piece_config;
+        board.config.mesh.insert(after.black_pieces, Color::RED);
+        board.undo();
+        // Overlays belong to the position they were drawn on
+        assert!(board.config.mesh.is_empty());
+        board.undo();
+        assert_eq!(board.config.piece_config, GameState::new().config);
+        board.undo();
+        assert_eq!(board.moves(), &[]);
+        board.redo();
+        board.redo();
+        board.redo();
+        assert_eq!(board.config.piece_config, after);
+        assert_eq!(board.moves().len(), 2);
+    }
+
+    #[test]
+    fn test_jump_and_branch() {
+        let mut board = board();
+        for square in ["f5", "d6", "c3"] {
+            play(&mut board, square);
+        }
+        board.jump(1);
+        assert_eq!(board.moves(), &[parse_square("f5").unwrap()]);
+        // Playing from an earlier position drops the moves after it
+        play(&mut board, "f6");
+        board.redo();
+        assert_eq!(board.moves().len(), 2);
+        assert_eq!(board.controller.positions().len(), 3);
+        board.jump(10);
+        assert_eq!(board.moves().len(), 2);
+    }
+
+    #[test]
+    fn test_panel() {
+        let mut board = board();
+        play(&mut board, "f5");
+        play(&mut board, "d6");
+        let rows: Vec<String> = board.panel().into_iter().map(|(row, _)| row).collect();
+        assert_eq!(rows, ["start", " 1. black f5", " 2. white d6"]);
+    }
+
+    #[test]
+    fn test_opening_name() {
+        let mut board = board().with_book(Book::new());
+        play(&mut board, "f5");
+        play(&mut board, "d6");
+        play(&mut board, "c3");
+        assert_eq!(board.panel()[0].0, "Perpendicular");
+        board.jump(1);
+        assert_eq!(board.panel()[0].0, "start");
+    }
+}
@@ -1,7 +1,29 @@
 use std::collections::HashMap;
 
+use bitboard::{Bitboard, Square};
+
+pub mod bitboard;
+pub mod book;
 pub mod cli;
+pub mod client;
+pub mod clock;
+pub mod controller;
+pub mod database;
+pub mod engine;
+pub mod eval;
+pub mod game;
+#[cfg(feature = "gui")]
 pub mod gui;
+pub mod notation;
+pub mod perft;
+pub mod players;
+pub mod protocol;
+pub mod search;
+pub mod server;
+pub mod solver;
+pub mod symmetry;
+pub mod tournament;
+pub mod transposition;
 
 pub const GENERAL_EDGE: u64 = 35604928818740736;
 pub const TOP_EDGE: u64 = 18374686479671623680;
@@ -23,313 +45,163 @@ pub const GENERAL_MESH: u64 = 460039;
 pub fn bitboard_position(row: u8, column: u8) -> u64 {
     let mut bitboard: u64 = 1;
     bitboard <<= ((7 - row) * 8) + (7 - column);
-    return bitboard;
+    bitboard
 }
 
-// Converts bitboard position into rows and columns
+// Converts bitboard position into rows and columns, of the first piece if
+// there are several
 pub fn bitboard_rowcol(position: u64) -> (u8, u8) {
-    let mut row: u8 = 0;
-    let mut column: u8 = 0;
-    'running: for i in 0..64 {
-        let mut pointer: u64 = 1;
-        pointer <<= 63 - i;
-        if pointer & position != 0 {
-            row = i / 8;
-            column = i % 8;
-            break 'running;
-        }
+    match Bitboard(position).first() {
+        Some(square) => (square.rank(), square.file()),
+        None => (0, 0),
     }
-    (row, column)
 }
 
-enum Position {
-    Inner6x6(u64),
-    LeftEdge(u64),
-    RightEdge(u64),
-    TopEdge(u64),
-    BottomEdge(u64),
-    LeftTop,
-    RightTop,
-    LeftBottom,
-    RightBottom,
-}
+// Shift amount and wrap-around mask for each of the 8 directions,
+// positive amounts shift left and negative amounts shift right
+const DIRECTIONS: [(i32, u64); 8] = [
+    (-1, !LEFT_EDGE),  // East
+    (1, !RIGHT_EDGE),  // West
+    (8, u64::MAX),     // North
+    (-8, u64::MAX),    // South
+    (7, !LEFT_EDGE),   // NorthEast
+    (-9, !LEFT_EDGE),  // SouthEast
+    (9, !RIGHT_EDGE),  // NorthWest
+    (-7, !RIGHT_EDGE), // SouthWest
+];
 
-impl Position {
-    fn new(position: u64) -> Position {
-        let is_inner_6x6 = position & GENERAL_EDGE != 0;
-        let is_edge_right = position & RIGHT_EDGE != 0;
-        let is_edge_left = position & LEFT_EDGE != 0;
-        let is_edge_top = position & TOP_EDGE != 0;
-        let is_edge_bottom = position & BOTTOM_EDGE != 0;
-        let is_lefttop = is_edge_left && is_edge_top;
-        let is_righttop = is_edge_right && is_edge_top;
-        let is_leftbottom = is_edge_left && is_edge_bottom;
-        let is_rightbottom = is_edge_right && is_edge_bottom;
-
-        if is_inner_6x6 {
-            return Position::Inner6x6(position);
-        } else if is_righttop {
-            return Position::RightTop;
-        } else if is_lefttop {
-            return Position::LeftTop;
-        } else if is_rightbottom {
-            return Position::RightBottom;
-        } else if is_leftbottom {
-            return Position::LeftBottom;
-        } else if is_edge_top {
-            return Position::TopEdge(position);
-        } else if is_edge_left {
-            return Position::LeftEdge(position);
-        } else if is_edge_right {
-            return Position::RightEdge(position);
-        } else {
-            return Position::BottomEdge(position);
-        }
+// Moves every piece one square in the given direction, dropping pieces
+// that would wrap around onto the opposite edge
+fn shift(board: u64, (amount, mask): (i32, u64)) -> u64 {
+    if amount > 0 {
+        (board << amount) & mask
+    } else {
+        (board >> -amount) & mask
     }
+}
 
-    // Checks surrounding 3x3 grid with position as center
-    // for neighbouring pieces
-    fn placable_mesh(&self) -> u64 {
-        match self {
-            Self::Inner6x6(position) => {
-                let (row, column) = bitboard_rowcol(*position);
-                GENERAL_MESH << (8 * (6 - row) + (6 - column))
-            }
-            Self::LeftEdge(position) => {
-                let (row, _) = bitboard_rowcol(*position);
-                LEFT_MESH << (8 * (6 - row))
-            }
-            Self::RightEdge(position) => {
-                let (row, _) = bitboard_rowcol(*position);
-                RIGHT_MESH << (8 * (6 - row))
-            }
-            Self::TopEdge(position) => {
-                let (_, column) = bitboard_rowcol(*position);
-                TOP_MESH << (6 - column)
-            }
-            Self::BottomEdge(position) => {
-                let (_, column) = bitboard_rowcol(*position);
-                BOTTOM_MESH << (6 - column)
-            }
-            Self::LeftTop => LEFT_TOP_MESH,
-            Self::RightTop => RIGHT_TOP_MESH,
-            Self::LeftBottom => LEFT_BOTTOM_MESH,
-            Self::RightBottom => RIGHT_BOTTOM_MESH,
-        }
-    }
+// Squares adjacent to any piece on the board in all 8 directions
+fn neighbours(board: u64) -> u64 {
+    DIRECTIONS
+        .iter()
+        .fold(0, |mesh, &direction| mesh | shift(board, direction))
 }
 
-// Returns the position for pieces on the board in Vec form
+// Returns the position for pieces on the board in Vec form, from h8 to a1,
+// Bitboard::iter goes over them without allocating
 pub fn piece_positions(board: u64) -> Option<Vec<u64>> {
-    let mut positions = Vec::new();
-    for i in 0..64 {
-        let position: u64 = 1 << i;
-        if board & position != 0 {
-            positions.push(position);
-        }
-    }
-    if positions.is_empty() {
+    if board == 0 {
         return None;
     }
-    return Some(positions);
+    Some(Bitboard(board).iter().rev().map(Square::bit).collect())
 }
 
 // Mesh for surrounding adjacent squares
-fn all_placable(board: u64) -> Option<u64> {
-    let mut possible_moves: u64 = 0;
-    let positions = piece_positions(board)?;
-    for position in positions {
-        if board & position != 0 {
-            let mesh = Position::new(position).placable_mesh();
-            possible_moves |= mesh & !board;
-        }
-    }
+pub fn all_placable(board: u64) -> Option<u64> {
+    let possible_moves = neighbours(board) & !board;
     if possible_moves == 0 {
         return None;
     }
-    return Some(possible_moves);
+    Some(possible_moves)
 }
 
-// Mesh searching in "shifted" direction for encapsulating piece
-fn directional_mesh(
-    position: u64,
-    ally: u64,
-    foe: u64,
-    shift: impl Fn(u64, u64) -> u64,
-    is_border: impl Fn(u64) -> bool,
-) -> Option<u64> {
-    let mut count = 1;
-    let mut mesh = 0;
-    loop {
-        let shifted = shift(position, count);
-        if (shifted & foe) != 0 {
-            mesh |= shifted;
-        } else if (shifted & ally) != 0 {
-            return Some(mesh);
-        } else {
-            return None;
-        }
-        if is_border(shifted) {
-            return None;
-        }
-        count += 1;
+// Mesh of every empty square where ally encapsulates at least one foe piece,
+// filling foe runs outwards from ally pieces one direction at a time
+pub fn legal_moves(ally: u64, foe: u64) -> u64 {
+    let empty = !(ally | foe);
+    let mut moves = 0;
+    for direction in DIRECTIONS {
+        let mut run = shift(ally, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        moves |= shift(run, direction) & empty;
     }
+    moves
 }
 
-// Mesh searching all 8 directions for encapsulating piece
-pub fn available_captures(ally: u64, foe: u64) -> Option<HashMap<u64, u64>> {
-    let psuedo_legal = all_placable(ally | foe)?;
-    let mut legal_moves = HashMap::new();
-    let positions = piece_positions(psuedo_legal)?;
-
-    for position in positions {
-        let mut all_star: u64 = 0;
-        let (row, col) = bitboard_rowcol(position);
-
-        // East
-        if col < 7 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| position >> shifted,
-                |position| {
-                    let (_row, column) = bitboard_rowcol(position);
-                    column == 7
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
-
-        // West
-        if col > 0 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| position << shifted,
-                |position| {
-                    let (_row, column) = bitboard_rowcol(position);
-                    column == 0
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
-
-        // North
-        if row > 0 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| position << shifted * 8,
-                |position| {
-                    let (row, _column) = bitboard_rowcol(position);
-                    row == 0
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
+// Mesh of foe pieces flipped by ally placing a piece on position,
+// empty if the move captures nothing
+pub fn flips(ally: u64, foe: u64, position: u64) -> u64 {
+    let mut flipped = 0;
+    for direction in DIRECTIONS {
+        let mut run = shift(position, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        run |= shift(run, direction) & foe;
+        let bounded = shift(run, direction) & ally;
+        // All ones when the run is capped by an ally piece, zero otherwise
+        flipped |= run & 0u64.wrapping_sub((bounded != 0) as u64);
+    }
+    flipped
+}
 
-        // South
-        if row < 7 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| position >> shifted * 8,
-                |position| {
-                    let (row, _column) = bitboard_rowcol(position);
-                    row == 7
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
+// Pairs of opposite directions making up the 4 lines through a square
+const AXES: [(usize, usize); 4] = [(0, 1), (2, 3), (4, 7), (5, 6)];
 
-        // NorthEast
-        if row > 0 && col < 7 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| (position << shifted * 8) >> shifted * 1,
-                |position| {
-                    let (row, column) = bitboard_rowcol(position);
-                    row == 0 || column == 7
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
+// Squares with no neighbour in the direction opposite to the given one
+fn board_edge(opposite: usize) -> u64 {
+    !shift(u64::MAX, DIRECTIONS[opposite])
+}
 
-        // SouthEast
-        if row < 7 && col < 7 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| (position >> shifted * 8) >> shifted * 1,
-                |position| {
-                    let (row, column) = bitboard_rowcol(position);
-                    row == 7 || column == 7
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
+// Occupied squares with every square up to the edge occupied too, looking in
+// the direction opposite to the given one
+fn filled_ray(occupied: u64, opposite: usize) -> u64 {
+    let mut filled = occupied & board_edge(opposite);
+    for _ in 0..7 {
+        filled |= occupied & shift(filled, DIRECTIONS[opposite]);
+    }
+    filled
+}
 
-        // NorthWest
-        if row > 0 && col > 0 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| (position << shifted * 8) << shifted * 1,
-                |position| {
-                    let (row, column) = bitboard_rowcol(position);
-                    row == 0 || column == 0
-                },
-            ) {
-                all_star |= mesh;
-            }
-        }
+// Mesh of ally pieces that can never be flipped, a piece is stable when along
+// each of the 4 lines through it the line is full, or it touches the edge or
+// another stable ally piece
+pub fn stable_discs(ally: u64, foe: u64) -> u64 {
+    let occupied = ally | foe;
+    let mut anchored = [0; 4];
+    for (axis, &(forward, backward)) in AXES.iter().enumerate() {
+        anchored[axis] = (filled_ray(occupied, forward) & filled_ray(occupied, backward))
+            | board_edge(forward)
+            | board_edge(backward);
+    }
 
-        // SouthWest
-        if row < 7 && col > 0 {
-            if let Some(mesh) = directional_mesh(
-                position,
-                ally,
-                foe,
-                |position, shifted| (position >> shifted * 8) << shifted * 1,
-                |position| {
-                    let (row, column) = bitboard_rowcol(position);
-                    row == 7 || column == 0
-                },
-            ) {
-                all_star |= mesh;
-            }
+    let mut stable = 0;
+    loop {
+        let mut next = ally;
+        for (axis, &(forward, backward)) in AXES.iter().enumerate() {
+            next &= anchored[axis]
+                | shift(stable, DIRECTIONS[forward])
+                | shift(stable, DIRECTIONS[backward]);
         }
-
-        if all_star != 0 {
-            legal_moves.insert(position, all_star);
+        if next == stable {
+            return stable;
         }
+        stable = next;
     }
+}
 
-    if legal_moves.is_empty() {
+// Mesh searching all 8 directions for encapsulating piece
+pub fn available_captures(ally: u64, foe: u64) -> Option<HashMap<u64, u64>> {
+    let mut moves = legal_moves(ally, foe);
+    if moves == 0 {
         return None;
     }
-    return Some(legal_moves);
+    let mut legal_moves = HashMap::new();
+    while moves != 0 {
+        let position = moves & moves.wrapping_neg();
+        legal_moves.insert(position, flips(ally, foe, position));
+        moves &= moves - 1;
+    }
+    Some(legal_moves)
 }
 
 pub fn valid_move(ally: u64, foe: u64, position: u64) -> bool {
-    if let Some(captures) = available_captures(ally, foe) {
-        return captures.contains_key(&position);
-    }
-    return false;
+    position.is_power_of_two() && legal_moves(ally, foe) & position != 0
 }
 
 #[cfg(test)]
@@ -352,7 +224,7 @@ mod tests {
     #[test]
     fn test_position_placement() {
         let position = 137438953472;
-        assert_eq!(Position::new(position).placable_mesh(), 123490778742784);
+        assert_eq!(neighbours(position), 123490778742784);
     }
 
     #[test]
@@ -371,4 +243,116 @@ mod tests {
         }
         assert_eq!(all_star, 4415293751296);
     }
+
+    #[test]
+    fn test_stable_discs() {
+        let top_row = bitboard_position(0, 0) | bitboard_position(0, 1) | bitboard_position(0, 2);
+        let loose = bitboard_position(0, 4) | bitboard_position(1, 1);
+        assert_eq!(
+            stable_discs(top_row | loose, bitboard_position(4, 4)),
+            top_row
+        );
+        // Every piece on a full board is stable
+        assert_eq!(stable_discs(TOP_EDGE, !TOP_EDGE), TOP_EDGE);
+        assert_eq!(stable_discs(0, u64::MAX), 0);
+    }
+
+    #[test]
+    fn test_stable_discs_never_flip() {
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        for _ in 0..200 {
+            let (mut ally, mut foe) = (34628173824, 68853694464);
+            let (mut ally_stable, mut foe_stable) = (0, 0);
+            loop {
+                let mut moves = legal_moves(ally, foe);
+                if moves == 0 {
+                    if legal_moves(foe, ally) == 0 {
+                        break;
+                    }
+                    (ally, foe, ally_stable, foe_stable) = (foe, ally, foe_stable, ally_stable);
+                    continue;
+                }
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                for _ in 0..seed % moves.count_ones() as u64 {
+                    moves &= moves - 1;
+                }
+                let position = moves & moves.wrapping_neg();
+                let flipped = flips(ally, foe, position);
+                assert_eq!(flipped & foe_stable, 0);
+                (ally, foe) = (foe & !flipped, ally | flipped | position);
+                (ally_stable, foe_stable) = (stable_discs(ally, foe), stable_discs(foe, ally));
+                assert_eq!(ally_stable & foe_stable, 0);
+            }
+        }
+    }
+
+    // Walks each direction square by square on rows and columns
+    fn naive_flips(ally: u64, foe: u64, position: u64) -> u64 {
+        let (row, column) = bitboard_rowcol(position);
+        let mut flipped = 0;
+        for (dr, dc) in [
+            (0, 1),
+            (0, -1),
+            (1, 0),
+            (-1, 0),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ] {
+            let (mut r, mut c) = (row as i32 + dr, column as i32 + dc);
+            let mut run = 0;
+            while (0..8).contains(&r) && (0..8).contains(&c) {
+                let square = bitboard_position(r as u8, c as u8);
+                if square & foe != 0 {
+                    run |= square;
+                } else {
+                    if square & ally != 0 {
+                        flipped |= run;
+                    }
+                    break;
+                }
+                r += dr;
+                c += dc;
+            }
+        }
+        flipped
+    }
+
+    #[test]
+    fn test_no_wrap_around_edges() {
+        // Ally on the left edge, foe on the right edge one row up
+        let ally = bitboard_position(3, 0);
+        let foe = bitboard_position(2, 7);
+        assert_eq!(legal_moves(ally, foe), 0);
+        assert_eq!(flips(ally, foe, bitboard_position(2, 6)), 0);
+    }
+
+    #[test]
+    fn test_flips_match_naive() {
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let occupied = seed | (seed >> 3);
+            let ally = occupied & seed.rotate_left(29);
+            let foe = occupied & !ally;
+            let mut naive_moves = 0;
+            for i in 0..64 {
+                let position = 1 << i;
+                if position & (ally | foe) != 0 {
+                    continue;
+                }
+                let expected = naive_flips(ally, foe, position);
+                assert_eq!(flips(ally, foe, position), expected);
+                if expected != 0 {
+                    naive_moves |= position;
+                }
+            }
+            assert_eq!(legal_moves(ally, foe), naive_moves);
+        }
+    }
 }
@@ -0,0 +1,304 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use crate::game::{Action, GameState, PieceConfig};
+use crate::{bitboard_position, bitboard_rowcol};
+
+// Written in place of a square when the side to move passes
+pub const PASS: &str = "pa";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    // Token is neither a square from a1 to h8 nor a pass
+    InvalidSquare { index: usize, token: String },
+    // Square or pass is not legal in the position reached so far
+    IllegalMove { index: usize, token: String },
+    // Board string or side to move of a position is malformed
+    InvalidPosition(String),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotationError::InvalidSquare { index, token } => {
+                write!(f, "move {}: {token:?} is not a square", index + 1)
+            }
+            NotationError::IllegalMove { index, token } => {
+                write!(f, "move {}: {token} is not legal", index + 1)
+            }
+            NotationError::InvalidPosition(reason) => write!(f, "invalid position: {reason}"),
+        }
+    }
+}
+
+impl Error for NotationError {}
+
+// Converts bitboard position into coordinates, columns a to h from the left
+// and rows 1 to 8 from the top
+pub fn square_name(position: u64) -> String {
+    let (row, column) = bitboard_rowcol(position);
+    format!("{}{}", (b'a' + column) as char, row + 1)
+}
+
+// Converts coordinates such as "f5" or "F5" into a bitboard position
+pub fn parse_square(text: &str) -> Option<u64> {
+    let &[column, row] = text.as_bytes() else {
+        return None;
+    };
+    let column = column.to_ascii_lowercase().checked_sub(b'a')?;
+    let row = row.checked_sub(b'1')?;
+    if column > 7 || row > 7 {
+        return None;
+    }
+    Some(bitboard_position(row, column))
+}
+
+pub fn action_name(action: Action) -> String {
+    match action {
+        Action::Place(position) => square_name(position),
+        Action::Pass => PASS.to_string(),
+    }
+}
+
+fn parse_action(token: &str) -> Option<Action> {
+    match token.to_ascii_lowercase().as_str() {
+        "pa" | "ps" | "--" => Some(Action::Pass),
+        _ => parse_square(token).map(Action::Place),
+    }
+}
+
+// Writes moves as a transcript such as "f5d6c3pa"
+pub fn write_transcript(moves: &[Action]) -> String {
+    moves.iter().map(|&action| action_name(action)).collect()
+}
+
+// Parses a transcript from the opening position, see parse_transcript_from
+pub fn parse_transcript(text: &str) -> Result<Vec<Action>, NotationError> {
+    parse_transcript_from(&GameState::new(), text)
+}
+
+// Parses and validates a transcript, whitespace between moves is ignored and
+// passes left out of the transcript are filled in where they are forced
+pub fn parse_transcript_from(start: &GameState, text: &str) -> Result<Vec<Action>, NotationError> {
+    let characters: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut state = *start;
+    let mut moves = Vec::new();
+    for (index, chunk) in characters.chunks(2).enumerate() {
+        let token: String = chunk.iter().collect();
+        let action = parse_action(&token).ok_or_else(|| NotationError::InvalidSquare {
+            index,
+            token: token.clone(),
+        })?;
+        if action != Action::Pass && state.must_pass() {
+            state = state.apply(Action::Pass).unwrap();
+            moves.push(Action::Pass);
+        }
+        state = state
+            .apply(action)
+            .ok_or(NotationError::IllegalMove { index, token })?;
+        moves.push(action);
+    }
+    Ok(moves)
+}
+
+// Every position of the game, starting with the given one
+pub fn replay(start: &GameState, moves: &[Action]) -> Option<Vec<GameState>> {
+    let mut positions = vec![*start];
+    for &action in moves {
+        positions.push(positions.last().unwrap().apply(action)?);
+    }
+    Some(positions)
+}
+
+// Writes a position as 64 squares from a1 to h8 row by row, X for black, O
+// for white and - for empty, followed by the side to move, X or O
+pub fn write_position(config: &PieceConfig) -> String {
+    let mut text = String::with_capacity(66);
+    for i in (0..64).rev() {
+        let position = 1 << i;
+        text.push(if config.black_pieces & position != 0 {
+            'X'
+        } else if config.white_pieces & position != 0 {
+            'O'
+        } else {
+            '-'
+        });
+    }
+    text.push(' ');
+    text.push(if config.blacks_play { 'X' } else { 'O' });
+    text
+}
+
+// Parses positions written by write_position, also taking lowercase, . for
+// empty squares and black or white for the side to move
+pub fn parse_position(text: &str) -> Result<PieceConfig, NotationError> {
+    let invalid = |reason: String| NotationError::InvalidPosition(reason);
+    let mut words = text.split_whitespace();
+    let board = words.next().unwrap_or_default();
+    let side = words
+        .next()
+        .ok_or_else(|| invalid("missing side to move".to_string()))?;
+    if let Some(extra) = words.next() {
+        return Err(invalid(format!(
+            "unexpected {extra:?} after the side to move"
+        )));
+    }
+    if board.chars().count() != 64 {
+        return Err(invalid(format!(
+            "board has {} squares instead of 64",
+            board.chars().count()
+        )));
+    }
+    let mut config = PieceConfig {
+        white_pieces: 0,
+        black_pieces: 0,
+        blacks_play: true,
+    };
+    for (i, square) in board.chars().enumerate() {
+        let position = 1 << (63 - i);
+        match square.to_ascii_uppercase() {
+            'X' => config.black_pieces |= position,
+            'O' => config.white_pieces |= position,
+            '-' | '.' => {}
+            square => {
+                return Err(invalid(format!(
+                    "{square:?} on {} is not X, O or -",
+                    square_name(position)
+                )))
+            }
+        }
+    }
+    config.blacks_play = match side.to_ascii_lowercase().as_str() {
+        "x" | "b" | "black" => true,
+        "o" | "w" | "white" => false,
+        side => return Err(invalid(format!("{side:?} is not a side to move"))),
+    };
+    Ok(config)
+}
+
+// Reads a position file holding a single written position
+pub fn read_position(path: impl AsRef<Path>) -> io::Result<PieceConfig> {
+    parse_position(&fs::read_to_string(path)?)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn save_position(path: impl AsRef<Path>, config: &PieceConfig) -> io::Result<()> {
+    fs::write(path, write_position(config) + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_names() {
+        assert_eq!(square_name(bitboard_position(0, 0)), "a1");
+        assert_eq!(square_name(bitboard_position(7, 7)), "h8");
+        assert_eq!(square_name(bitboard_position(4, 5)), "f5");
+        assert_eq!(parse_square("F5"), Some(bitboard_position(4, 5)));
+        assert_eq!(parse_square("i1"), None);
+        assert_eq!(parse_square("a9"), None);
+        assert_eq!(parse_square("a"), None);
+        for i in 0..64 {
+            assert_eq!(parse_square(&square_name(1 << i)), Some(1 << i));
+        }
+    }
+
+    #[test]
+    fn test_transcript_round_trip() {
+        let moves = parse_transcript("f5d6c3d3c4").unwrap();
+        assert_eq!(moves.len(), 5);
+        assert_eq!(write_transcript(&moves), "f5d6c3d3c4");
+        assert_eq!(parse_transcript("F5 D6 c3").unwrap(), moves[..3].to_vec());
+    }
+
+    #[test]
+    fn test_illegal_and_invalid_moves() {
+        assert_eq!(
+            parse_transcript("f5f5"),
+            Err(NotationError::IllegalMove {
+                index: 1,
+                token: "f5".to_string()
+            })
+        );
+        assert_eq!(
+            parse_transcript("f5z9"),
+            Err(NotationError::InvalidSquare {
+                index: 1,
+                token: "z9".to_string()
+            })
+        );
+        assert!(parse_transcript("pa").is_err());
+    }
+
+    #[test]
+    fn test_shortest_game() {
+        let text = "f5d6c5f4e3f6g5e6e7";
+        let moves = parse_transcript(text).unwrap();
+        let positions = replay(&GameState::new(), &moves).unwrap();
+        assert!(positions.last().unwrap().is_terminal());
+        assert_eq!(write_transcript(&moves), text);
+    }
+
+    #[test]
+    fn test_forced_passes() {
+        // White cannot move, black can take c1
+        let start = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0),
+            white_pieces: bitboard_position(0, 1),
+            blacks_play: false,
+        });
+        let moves = parse_transcript_from(&start, "c1").unwrap();
+        assert_eq!(
+            moves,
+            vec![Action::Pass, Action::Place(bitboard_position(0, 2))]
+        );
+        assert_eq!(write_transcript(&moves), "pac1");
+        assert_eq!(parse_transcript_from(&start, "pac1").unwrap(), moves);
+    }
+
+    #[test]
+    fn test_position_round_trip() {
+        let start = GameState::new().config;
+        let text = write_position(&start);
+        assert_eq!(
+            text,
+            "---------------------------OX------XO--------------------------- X"
+        );
+        assert_eq!(parse_position(&text), Ok(start));
+        let state = replay(&start.into(), &parse_transcript("f5d6c3").unwrap())
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(
+            parse_position(&write_position(&state.config)),
+            Ok(state.config)
+        );
+        assert_eq!(
+            parse_position(
+                &text
+                    .replace('-', ".")
+                    .to_lowercase()
+                    .replace(" x", " white")
+            ),
+            Ok(PieceConfig {
+                blacks_play: false,
+                ..start
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_positions() {
+        let board = "-".repeat(64);
+        assert!(parse_position(&board).is_err());
+        assert!(parse_position(&format!("{board} X extra")).is_err());
+        assert!(parse_position(&format!("{board} purple")).is_err());
+        assert!(parse_position("-- X").is_err());
+        assert_eq!(
+            parse_position(&format!("Z{} O", &board[1..])),
+            Err(NotationError::InvalidPosition(
+                "'Z' on a1 is not X, O or -".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::game::{Action, GameState};
+
+// Counts the leaves of the game tree depth plies deep, the usual way for
+// reversi: a forced pass is a ply of its own and a finished game is a leaf
+// even when it ends early
+pub fn perft(state: &GameState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = state.move_mask();
+    if moves == 0 {
+        return match state.apply(Action::Pass) {
+            Some(passed) => perft(&passed, depth - 1),
+            None => 1,
+        };
+    }
+    // Every placement is a leaf on the last ply, no need to play them
+    if depth == 1 {
+        return moves.count_ones() as u64;
+    }
+    state
+        .legal_moves()
+        .into_iter()
+        .map(|action| perft(&state.apply(action).unwrap(), depth - 1))
+        .sum()
+}
+
+// Leaf count below every legal action of the position, empty once the game
+// is over
+pub fn divide(state: &GameState, depth: u32) -> Vec<(Action, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    state
+        .legal_moves()
+        .into_iter()
+        .map(|action| (action, perft(&state.apply(action).unwrap(), depth - 1)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::PieceConfig;
+    use crate::notation::{parse_square, parse_transcript, replay};
+    use crate::{available_captures, bitboard_position};
+
+    #[test]
+    fn test_start_position() {
+        let counts = [1, 4, 12, 56, 244, 1396, 8200, 55092, 390216];
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(perft(&GameState::new(), depth as u32), count);
+        }
+    }
+
+    #[test]
+    fn test_deep_start_position() {
+        // The shortest games end right on the last ply
+        assert_eq!(perft(&GameState::new(), 9), 3005288);
+    }
+
+    #[test]
+    #[ignore = "takes seconds without optimizations"]
+    fn test_games_ending_early() {
+        // First depths with games that end before the last ply
+        assert_eq!(perft(&GameState::new(), 10), 24571284);
+        assert_eq!(perft(&GameState::new(), 11), 212258800);
+    }
+
+    #[test]
+    fn test_divide() {
+        let state = GameState::new();
+        let split = divide(&state, 5);
+        assert_eq!(split.len(), 4);
+        // The opening is symmetric, every first move leads to the same count
+        assert!(split.iter().all(|&(_, count)| count == 1396 / 4));
+        assert!(divide(&state, 0).is_empty());
+    }
+
+    #[test]
+    fn test_passes_and_game_end() {
+        // White is stuck after black takes c1, black still has g7
+        let stuck = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0) | bitboard_position(7, 7),
+            white_pieces: bitboard_position(0, 1) | bitboard_position(6, 6),
+            blacks_play: true,
+        });
+        let c1 = Action::Place(parse_square("c1").unwrap());
+        let state = stuck.apply(c1).unwrap();
+        assert!(state.must_pass());
+        assert_eq!(divide(&state, 2), vec![(Action::Pass, 1)]);
+        assert_eq!(perft(&state, 1), 1);
+        // Black takes g7 after the pass, then nobody can move
+        assert_eq!(perft(&state, 2), 1);
+        assert_eq!(perft(&state, 5), 1);
+
+        let moves = parse_transcript("f5d6c5f4e3f6g5e6e7").unwrap();
+        let finished = *replay(&GameState::new(), &moves).unwrap().last().unwrap();
+        assert_eq!(perft(&finished, 3), 1);
+        assert!(divide(&finished, 3).is_empty());
+    }
+
+    // Same count walking the moves from available_captures with plain bit
+    // operations instead of GameState
+    fn capture_perft(ally: u64, foe: u64, depth: u32, passed: bool) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let Some(captures) = available_captures(ally, foe) else {
+            // Two passes in a row end the game
+            if passed || available_captures(foe, ally).is_none() {
+                return 1;
+            }
+            return capture_perft(foe, ally, depth - 1, true);
+        };
+        captures
+            .into_iter()
+            .map(|(position, flipped)| {
+                capture_perft(foe & !flipped, ally | flipped | position, depth - 1, false)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_available_captures() {
+        let start = GameState::new();
+        let (ally, foe) = start.config.ally_foe();
+        for depth in 0..=7 {
+            assert_eq!(capture_perft(ally, foe, depth, false), perft(&start, depth));
+        }
+    }
+}
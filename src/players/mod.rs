@@ -0,0 +1,197 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use crate::book::Book;
+use crate::controller::{Move, Player};
+use crate::engine::{parse_response, parse_vertex, side_name, vertex_name};
+use crate::eval::{Evaluator, PatternEvaluator, Weights};
+use crate::game::{Action, GameState, PieceConfig};
+use crate::search::{search_with_evaluator, SearchLimits};
+use crate::transposition::TranspositionTable;
+
+// Computer opponent picking moves with the alpha-beta search
+pub struct AiPlayer {
+    limits: SearchLimits,
+    book: Option<Book>,
+    table: TranspositionTable,
+    evaluator: Box<dyn Evaluator>,
+}
+
+impl AiPlayer {
+    pub fn new(limits: SearchLimits) -> AiPlayer {
+        AiPlayer {
+            limits,
+            book: None,
+            table: TranspositionTable::default(),
+            evaluator: Box::new(PatternEvaluator::default()),
+        }
+    }
+
+    // Sizes the transposition table kept between moves to the given bytes
+    pub fn with_memory(mut self, bytes: usize) -> AiPlayer {
+        self.table = TranspositionTable::with_memory(bytes);
+        self
+    }
+
+    // Scores positions with tuned weights, such as ones loaded from a file
+    pub fn with_weights(mut self, weights: Weights) -> AiPlayer {
+        self.evaluator = Box::new(PatternEvaluator::new(weights));
+        self
+    }
+
+    // Plays the book's best move while the position is in it
+    pub fn with_book(mut self, book: Book) -> AiPlayer {
+        self.book = Some(book);
+        self
+    }
+}
+
+impl Player for AiPlayer {
+    fn play_move(&mut self, config: &PieceConfig) -> Move {
+        let state = GameState::from(*config);
+        if let Some(position) = self.book.as_ref().and_then(|book| book.best_move(config)) {
+            if state.move_mask() & position != 0 {
+                return Move::Position(position);
+            }
+        }
+        let result = search_with_evaluator(&state, &self.limits, &mut self.table, &*self.evaluator);
+        match result.best {
+            Some(Action::Place(position)) => Move::Position(position),
+            // Passes and finished games are left to the board
+            _ => Move::Board,
+        }
+    }
+
+    fn enemy_move(&mut self, _current_move: u64) {}
+}
+
+// External engine speaking the line protocol of the engine module, run as a
+// subprocess that is told every move of the game
+pub struct EnginePlayer {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    // The game as the engine knows it
+    state: GameState,
+}
+
+impl EnginePlayer {
+    pub fn spawn(command: &mut Command) -> io::Result<EnginePlayer> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let mut engine = EnginePlayer {
+            child,
+            input,
+            output,
+            state: GameState::new(),
+        };
+        engine.send("boardsize 8")?;
+        engine.send("clear_board")?;
+        Ok(engine)
+    }
+
+    // Sends a command and waits for its response, engine errors are returned
+    // as io errors
+    pub fn send(&mut self, command: &str) -> io::Result<String> {
+        writeln!(self.input, "{command}")?;
+        self.input.flush()?;
+        let mut response = String::new();
+        loop {
+            let mut line = String::new();
+            if self.output.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "engine exited",
+                ));
+            }
+            if line.trim().is_empty() {
+                // Blank lines before a response are skipped
+                if response.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            response.push_str(&line);
+        }
+        parse_response(&response).map_err(io::Error::other)
+    }
+
+    // Tells the engine about an action of the side to move
+    fn play(&mut self, action: Action) -> io::Result<()> {
+        let next = self
+            .state
+            .apply(action)
+            .ok_or_else(|| io::Error::other("engine is out of sync with the board"))?;
+        let side = side_name(self.state.side_to_move());
+        self.send(&format!("play {side} {}", vertex_name(action)))?;
+        self.state = next;
+        Ok(())
+    }
+
+    // Passes the board plays on its own are sent explicitly
+    fn catch_up(&mut self, config: &PieceConfig) -> io::Result<()> {
+        if self.state.config.blacks_play != config.blacks_play && self.state.must_pass() {
+            self.play(Action::Pass)?;
+        }
+        if self.state.config != *config {
+            return Err(io::Error::other("engine is out of sync with the board"));
+        }
+        Ok(())
+    }
+
+    fn genmove(&mut self, config: &PieceConfig) -> io::Result<Action> {
+        self.catch_up(config)?;
+        let side = side_name(self.state.side_to_move());
+        let vertex = self.send(&format!("genmove {side}"))?;
+        let action = parse_vertex(&vertex)
+            .ok_or_else(|| io::Error::other(format!("engine played {vertex:?}")))?;
+        self.state = self
+            .state
+            .apply(action)
+            .ok_or_else(|| io::Error::other(format!("engine played illegal {vertex}")))?;
+        Ok(action)
+    }
+}
+
+impl Player for EnginePlayer {
+    fn play_move(&mut self, config: &PieceConfig) -> Move {
+        if GameState::from(*config).is_terminal() {
+            return Move::Board;
+        }
+        match self.genmove(config) {
+            Ok(Action::Place(position)) => Move::Position(position),
+            // Passes are left to the board
+            Ok(Action::Pass) => Move::Board,
+            Err(error) => {
+                eprintln!("engine error: {error}");
+                Move::Board
+            }
+        }
+    }
+
+    fn enemy_move(&mut self, current_move: u64) {
+        if self.state.must_pass() {
+            if let Err(error) = self.play(Action::Pass) {
+                eprintln!("engine error: {error}");
+            }
+        }
+        if let Err(error) = self.play(Action::Place(current_move)) {
+            eprintln!("engine error: {error}");
+        }
+    }
+}
+
+impl Drop for EnginePlayer {
+    fn drop(&mut self) {
+        // Engines that ignore quit are not waited for
+        if self.send("quit").is_ok() {
+            let _ = self.child.wait();
+        } else {
+            let _ = self.child.kill();
+        }
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use crate::clock::Remaining;
+use crate::game::{Action, PieceConfig, Side};
+
+// Bumped whenever the layout of a message changes
+pub const VERSION: u8 = 5;
+
+// Frames larger than this are rejected before anything is allocated
+pub const MAX_FRAME: usize = 64 * 1024;
+// Longest player name sent in a game list, longer ones are cut short
+pub const MAX_NAME: usize = 64;
+
+// Every frame is a little endian u32 length followed by that many bytes:
+// the protocol version, the message tag and the payload
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    // First message from either side, names the program on the other end
+    Hello {
+        name: String,
+    },
+    // Token the server hands out after hello, a client that lost its
+    // connection sends it instead of hello to get its seat back
+    Session(u64),
+    Resume(u64),
+    // Answer to resume, every action of the game so far including passes
+    History {
+        side: Side,
+        moves: Vec<Action>,
+    },
+    // The opponent dropped and has this long to come back
+    Paused {
+        seconds: u32,
+    },
+    Unpaused,
+    // Lists the games waiting for an opponent and the ones being played
+    ListGames,
+    GameList(Vec<GameInfo>),
+    // Opens a game where the client plays the given side
+    CreateGame(Side),
+    GameCreated(u32),
+    JoinGame(u32),
+    // Watches a game without playing, answered with its current position
+    Spectate(u32),
+    Position(PieceConfig),
+    // Sent to both players once a game starts
+    ColorAssigned(Side),
+    // Bitboard position of the placed disc
+    Move(u64),
+    Pass,
+    Resign,
+    // Time left on both clocks, sent whenever a clock starts or stops, and
+    // the byo-yomi period length to count down with
+    Clock {
+        black: Remaining,
+        white: Remaining,
+        period: Duration,
+    },
+    // The side ran out of time and lost, followed by game over
+    TimeOut(Side),
+    // Final disc counts
+    GameOver {
+        black: u8,
+        white: u8,
+    },
+    Error(String),
+    // Keeps idle connections alive, ignored by the receiver
+    Ping(u64),
+}
+
+// Game in the lobby and the side its host plays, guest is None until
+// someone joins
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameInfo {
+    pub id: u32,
+    pub host: String,
+    pub side: Side,
+    pub guest: Option<String>,
+}
+
+const HELLO: u8 = 0;
+const LIST_GAMES: u8 = 1;
+const GAME_LIST: u8 = 2;
+const CREATE_GAME: u8 = 3;
+const GAME_CREATED: u8 = 4;
+const JOIN_GAME: u8 = 5;
+const COLOR_ASSIGNED: u8 = 6;
+const MOVE: u8 = 7;
+const PASS: u8 = 8;
+const RESIGN: u8 = 9;
+const GAME_OVER: u8 = 10;
+const ERROR: u8 = 11;
+const PING: u8 = 12;
+const SPECTATE: u8 = 13;
+const POSITION: u8 = 14;
+const SESSION: u8 = 15;
+const RESUME: u8 = 16;
+const HISTORY: u8 = 17;
+const PAUSED: u8 = 18;
+const UNPAUSED: u8 = 19;
+const CLOCK: u8 = 20;
+const TIME_OUT: u8 = 21;
+
+// Square index of each action, passes are written as 64
+const PASS_INDEX: u8 = 64;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn encode_side(side: Side) -> u8 {
+    match side {
+        Side::Black => 0,
+        Side::White => 1,
+    }
+}
+
+fn decode_side(payload: &[u8]) -> io::Result<Side> {
+    match payload {
+        [0] => Ok(Side::Black),
+        [1] => Ok(Side::White),
+        _ => Err(invalid_data(format!("invalid side {payload:?}"))),
+    }
+}
+
+fn decode_u64(payload: &[u8]) -> io::Result<u64> {
+    let bytes = payload
+        .try_into()
+        .map_err(|_| invalid_data(format!("expected 8 bytes, got {}", payload.len())))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn decode_u32(payload: &[u8]) -> io::Result<u32> {
+    let bytes = payload
+        .try_into()
+        .map_err(|_| invalid_data(format!("expected 4 bytes, got {}", payload.len())))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn encode_name(payload: &mut Vec<u8>, name: &str) {
+    payload.extend((name.len() as u16).to_le_bytes());
+    payload.extend(name.as_bytes());
+}
+
+// Splits off the first count bytes
+fn take(payload: &[u8], count: usize) -> io::Result<(&[u8], &[u8])> {
+    if payload.len() < count {
+        return Err(invalid_data("game list is truncated".to_string()));
+    }
+    Ok(payload.split_at(count))
+}
+
+fn decode_name(payload: &[u8]) -> io::Result<(String, &[u8])> {
+    let (length, rest) = take(payload, 2)?;
+    let (name, rest) = take(rest, u16::from_le_bytes([length[0], length[1]]) as usize)?;
+    Ok((decode_text(name)?, rest))
+}
+
+// Name cut to at most MAX_NAME bytes without splitting a character
+fn short_name(name: &str) -> &str {
+    let mut end = name.len().min(MAX_NAME);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+// Each game is its id, the host side, the host name and, once the game has
+// started, a 1 followed by the guest name or a 0 otherwise. Games that would
+// make the frame too large for read_message are left out
+fn encode_games(games: &[GameInfo]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for game in games {
+        let mut entry = game.id.to_le_bytes().to_vec();
+        entry.push(encode_side(game.side));
+        encode_name(&mut entry, short_name(&game.host));
+        match &game.guest {
+            Some(guest) => {
+                entry.push(1);
+                encode_name(&mut entry, short_name(guest));
+            }
+            None => entry.push(0),
+        }
+        // Room for the version and tag
+        if payload.len() + entry.len() + 2 > MAX_FRAME {
+            break;
+        }
+        payload.extend(entry);
+    }
+    payload
+}
+
+fn decode_games(mut payload: &[u8]) -> io::Result<Vec<GameInfo>> {
+    let mut games = Vec::new();
+    while !payload.is_empty() {
+        let (header, rest) = take(payload, 5)?;
+        let (host, rest) = decode_name(rest)?;
+        let (started, rest) = take(rest, 1)?;
+        let (guest, rest) = match started {
+            [0] => (None, rest),
+            [1] => {
+                let (guest, rest) = decode_name(rest)?;
+                (Some(guest), rest)
+            }
+            _ => return Err(invalid_data(format!("invalid game state {started:?}"))),
+        };
+        games.push(GameInfo {
+            id: decode_u32(&header[..4])?,
+            host,
+            side: decode_side(&header[4..])?,
+            guest,
+        });
+        payload = rest;
+    }
+    Ok(games)
+}
+
+fn encode_history(side: Side, moves: &[Action]) -> Vec<u8> {
+    let mut payload = vec![encode_side(side)];
+    payload.extend(moves.iter().map(|action| match action {
+        Action::Place(position) => position.trailing_zeros() as u8,
+        Action::Pass => PASS_INDEX,
+    }));
+    payload
+}
+
+fn decode_history(payload: &[u8]) -> io::Result<Message> {
+    let [side, moves @ ..] = payload else {
+        return Err(invalid_data("history is empty".to_string()));
+    };
+    let moves = moves
+        .iter()
+        .map(|&index| match index {
+            PASS_INDEX => Ok(Action::Pass),
+            0..=63 => Ok(Action::Place(1 << index)),
+            _ => Err(invalid_data(format!("{index} is not a square"))),
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(Message::History {
+        side: decode_side(&[*side])?,
+        moves,
+    })
+}
+
+// Black discs, white discs and whether black moves next
+fn encode_position(config: &PieceConfig) -> Vec<u8> {
+    let mut payload = config.black_pieces.to_le_bytes().to_vec();
+    payload.extend(config.white_pieces.to_le_bytes());
+    payload.push(config.blacks_play as u8);
+    payload
+}
+
+fn decode_position(payload: &[u8]) -> io::Result<PieceConfig> {
+    if payload.len() != 17 {
+        return Err(invalid_data(format!(
+            "expected 17 bytes, got {}",
+            payload.len()
+        )));
+    }
+    let config = PieceConfig {
+        black_pieces: decode_u64(&payload[..8])?,
+        white_pieces: decode_u64(&payload[8..16])?,
+        blacks_play: match payload[16] {
+            0 => false,
+            1 => true,
+            side => return Err(invalid_data(format!("invalid side {side}"))),
+        },
+    };
+    if config.black_pieces & config.white_pieces != 0 {
+        return Err(invalid_data("discs overlap".to_string()));
+    }
+    Ok(config)
+}
+
+fn millis(duration: Duration) -> [u8; 4] {
+    (duration.as_millis().min(u32::MAX as u128) as u32).to_le_bytes()
+}
+
+// Milliseconds and periods left for black, the same for white and the
+// period length in milliseconds
+fn encode_clock(black: &Remaining, white: &Remaining, period: Duration) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(20);
+    for remaining in [black, white] {
+        payload.extend(millis(remaining.time));
+        payload.extend(remaining.periods.to_le_bytes());
+    }
+    payload.extend(millis(period));
+    payload
+}
+
+fn decode_clock(payload: &[u8]) -> io::Result<Message> {
+    if payload.len() != 20 {
+        return Err(invalid_data(format!(
+            "expected 20 bytes, got {}",
+            payload.len()
+        )));
+    }
+    let millis = |offset: usize| -> io::Result<Duration> {
+        Ok(Duration::from_millis(
+            decode_u32(&payload[offset..offset + 4])?.into(),
+        ))
+    };
+    Ok(Message::Clock {
+        black: Remaining {
+            time: millis(0)?,
+            periods: decode_u32(&payload[4..8])?,
+        },
+        white: Remaining {
+            time: millis(8)?,
+            periods: decode_u32(&payload[12..16])?,
+        },
+        period: millis(16)?,
+    })
+}
+
+fn decode_text(payload: &[u8]) -> io::Result<String> {
+    String::from_utf8(payload.to_vec()).map_err(|_| invalid_data("text is not UTF-8".to_string()))
+}
+
+fn expect_empty(payload: &[u8]) -> io::Result<()> {
+    if !payload.is_empty() {
+        return Err(invalid_data(format!(
+            "unexpected {} byte payload",
+            payload.len()
+        )));
+    }
+    Ok(())
+}
+
+// Full frame including the length prefix
+pub fn encode(message: &Message) -> Vec<u8> {
+    let (tag, payload) = match message {
+        Message::Hello { name } => (HELLO, name.as_bytes().to_vec()),
+        Message::Session(token) => (SESSION, token.to_le_bytes().to_vec()),
+        Message::Resume(token) => (RESUME, token.to_le_bytes().to_vec()),
+        Message::History { side, moves } => (HISTORY, encode_history(*side, moves)),
+        Message::Paused { seconds } => (PAUSED, seconds.to_le_bytes().to_vec()),
+        Message::Unpaused => (UNPAUSED, Vec::new()),
+        Message::ListGames => (LIST_GAMES, Vec::new()),
+        Message::GameList(games) => (GAME_LIST, encode_games(games)),
+        Message::CreateGame(side) => (CREATE_GAME, vec![encode_side(*side)]),
+        Message::GameCreated(id) => (GAME_CREATED, id.to_le_bytes().to_vec()),
+        Message::JoinGame(id) => (JOIN_GAME, id.to_le_bytes().to_vec()),
+        Message::Spectate(id) => (SPECTATE, id.to_le_bytes().to_vec()),
+        Message::Position(config) => (POSITION, encode_position(config)),
+        Message::ColorAssigned(side) => (COLOR_ASSIGNED, vec![encode_side(*side)]),
+        Message::Move(position) => (MOVE, position.to_le_bytes().to_vec()),
+        Message::Pass => (PASS, Vec::new()),
+        Message::Resign => (RESIGN, Vec::new()),
+        Message::Clock {
+            black,
+            white,
+            period,
+        } => (CLOCK, encode_clock(black, white, *period)),
+        Message::TimeOut(side) => (TIME_OUT, vec![encode_side(*side)]),
+        Message::GameOver { black, white } => (GAME_OVER, vec![*black, *white]),
+        Message::Error(text) => (ERROR, text.as_bytes().to_vec()),
+        Message::Ping(nonce) => (PING, nonce.to_le_bytes().to_vec()),
+    };
+    let mut frame = Vec::with_capacity(6 + payload.len());
+    frame.extend((2 + payload.len() as u32).to_le_bytes());
+    frame.push(VERSION);
+    frame.push(tag);
+    frame.extend(payload);
+    frame
+}
+
+// Decodes the bytes following the length prefix
+pub fn decode(frame: &[u8]) -> io::Result<Message> {
+    let [version, tag, payload @ ..] = frame else {
+        return Err(invalid_data("frame is too short".to_string()));
+    };
+    if *version != VERSION {
+        return Err(invalid_data(format!(
+            "protocol version {version} is not supported, expected {VERSION}"
+        )));
+    }
+    let message = match *tag {
+        HELLO => Message::Hello {
+            name: decode_text(payload)?,
+        },
+        SESSION => Message::Session(decode_u64(payload)?),
+        RESUME => Message::Resume(decode_u64(payload)?),
+        HISTORY => decode_history(payload)?,
+        PAUSED => Message::Paused {
+            seconds: decode_u32(payload)?,
+        },
+        UNPAUSED => {
+            expect_empty(payload)?;
+            Message::Unpaused
+        }
+        LIST_GAMES => {
+            expect_empty(payload)?;
+            Message::ListGames
+        }
+        GAME_LIST => Message::GameList(decode_games(payload)?),
+        CREATE_GAME => Message::CreateGame(decode_side(payload)?),
+        GAME_CREATED => Message::GameCreated(decode_u32(payload)?),
+        JOIN_GAME => Message::JoinGame(decode_u32(payload)?),
+        SPECTATE => Message::Spectate(decode_u32(payload)?),
+        POSITION => Message::Position(decode_position(payload)?),
+        COLOR_ASSIGNED => Message::ColorAssigned(decode_side(payload)?),
+        CLOCK => decode_clock(payload)?,
+        TIME_OUT => Message::TimeOut(decode_side(payload)?),
+        MOVE => {
+            let position = decode_u64(payload)?;
+            if position.count_ones() != 1 {
+                return Err(invalid_data(format!("{position} is not a single square")));
+            }
+            Message::Move(position)
+        }
+        PASS => {
+            expect_empty(payload)?;
+            Message::Pass
+        }
+        RESIGN => {
+            expect_empty(payload)?;
+            Message::Resign
+        }
+        GAME_OVER => match *payload {
+            [black, white] => Message::GameOver { black, white },
+            _ => return Err(invalid_data("expected 2 byte score".to_string())),
+        },
+        ERROR => Message::Error(decode_text(payload)?),
+        PING => Message::Ping(decode_u64(payload)?),
+        _ => return Err(invalid_data(format!("unknown message tag {tag}"))),
+    };
+    Ok(message)
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    writer.write_all(&encode(message))?;
+    writer.flush()
+}
+
+// Blocks until a whole frame has arrived, however it was split up on the way
+pub fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME {
+        return Err(invalid_data(format!(
+            "frame of {length} bytes is too large"
+        )));
+    }
+    let mut frame = vec![0; length];
+    reader.read_exact(&mut frame)?;
+    decode(&frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard_position;
+    use crate::game::GameState;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::Hello {
+                name: "reversi".to_string(),
+            },
+            Message::Session(u64::MAX),
+            Message::Resume(42),
+            Message::History {
+                side: Side::White,
+                moves: vec![Action::Place(bitboard_position(4, 5)), Action::Pass],
+            },
+            Message::History {
+                side: Side::Black,
+                moves: Vec::new(),
+            },
+            Message::Paused { seconds: 60 },
+            Message::Unpaused,
+            Message::Clock {
+                black: Remaining {
+                    time: Duration::from_millis(299_250),
+                    periods: 0,
+                },
+                white: Remaining {
+                    time: Duration::from_secs(30),
+                    periods: 5,
+                },
+                period: Duration::from_secs(30),
+            },
+            Message::TimeOut(Side::Black),
+            Message::ListGames,
+            Message::GameList(vec![
+                GameInfo {
+                    id: 3,
+                    host: "alice".to_string(),
+                    side: Side::White,
+                    guest: Some("bob".to_string()),
+                },
+                GameInfo {
+                    id: 9,
+                    host: String::new(),
+                    side: Side::Black,
+                    guest: None,
+                },
+            ]),
+            Message::GameList(Vec::new()),
+            Message::CreateGame(Side::White),
+            Message::GameCreated(3),
+            Message::JoinGame(3),
+            Message::Spectate(3),
+            Message::Position(GameState::new().config),
+            Message::ColorAssigned(Side::Black),
+            Message::Move(bitboard_position(0, 0)),
+            Message::Pass,
+            Message::Resign,
+            Message::GameOver {
+                black: 40,
+                white: 24,
+            },
+            Message::Error("not your turn".to_string()),
+            Message::Ping(7),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for message in messages() {
+            let frame = encode(&message);
+            assert_eq!(decode(&frame[4..]).unwrap(), message);
+            assert_eq!(read_message(&mut frame.as_slice()).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_coalesced_and_split_frames() {
+        // Several frames in one buffer come out one message at a time
+        let stream: Vec<u8> = messages().iter().flat_map(encode).collect();
+        let mut reader = stream.as_slice();
+        for message in messages() {
+            assert_eq!(read_message(&mut reader).unwrap(), message);
+        }
+        assert!(reader.is_empty());
+
+        // A frame cut short is an error rather than a bogus message
+        let frame = encode(&Message::Move(1));
+        assert!(read_message(&mut &frame[..frame.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[VERSION + 1, PASS]).is_err());
+        assert!(decode(&[VERSION, 200]).is_err());
+        assert!(decode(&[VERSION, PASS, 0]).is_err());
+        assert!(decode(&[VERSION, CREATE_GAME, 2]).is_err());
+        assert!(decode(&[VERSION, HISTORY]).is_err());
+        assert!(decode(&[VERSION, CLOCK, 0, 0, 0, 0]).is_err());
+        assert!(decode(&[VERSION, TIME_OUT, 2]).is_err());
+        assert!(decode(&[VERSION, HISTORY, 0, 65]).is_err());
+        assert!(decode(&[VERSION, GAME_LIST, 1, 0, 0, 0, 0, 5, 0, b'a']).is_err());
+        assert!(decode(&[VERSION, GAME_LIST, 1, 0, 0, 0, 0, 1, 0, b'a', 2]).is_err());
+        let mut frame = vec![VERSION, POSITION];
+        frame.extend([1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(decode(&frame).is_err());
+        // Moves must name exactly one square
+        let mut frame = vec![VERSION, MOVE];
+        frame.extend(3u64.to_le_bytes());
+        assert!(decode(&frame).is_err());
+
+        let mut oversized = (MAX_FRAME as u32 + 1).to_le_bytes().to_vec();
+        oversized.extend([VERSION, PASS]);
+        assert!(read_message(&mut oversized.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_large_game_list() {
+        let long = "é".repeat(MAX_NAME);
+        let games: Vec<GameInfo> = (0..2000)
+            .map(|id| GameInfo {
+                id,
+                host: long.clone(),
+                side: Side::Black,
+                guest: Some(long.clone()),
+            })
+            .collect();
+        let frame = encode(&Message::GameList(games));
+        assert!(frame.len() - 4 <= MAX_FRAME);
+        let Message::GameList(read) = read_message(&mut frame.as_slice()).unwrap() else {
+            panic!("expected a game list");
+        };
+        // The first games fit with their names cut short on a character
+        assert!(read.len() > 100 && read.len() < 2000);
+        assert_eq!(read[0].id, 0);
+        assert_eq!(read[0].host, "é".repeat(MAX_NAME / 2));
+        assert_eq!(read[0].guest.as_deref(), Some(read[0].host.as_str()));
+    }
+}
@@ -0,0 +1,380 @@
+use std::time::{Duration, Instant};
+
+use crate::eval::{Evaluator, PatternEvaluator};
+use crate::game::{Action, GameState};
+use crate::transposition::{hash_bitboards, Bound, TranspositionTable};
+use crate::{flips, legal_moves};
+
+// Larger than any reachable score, used as the initial search window
+pub const INFINITY: i32 = 1_000_000;
+// Offset added to finished games so a won ending beats any heuristic score
+pub const WIN_SCORE: i32 = 100_000;
+
+const CORNERS: u64 = 0x8100000000000081;
+// Nodes visited between looks at the clock
+const CHECK_INTERVAL: u32 = 1024;
+
+// How long and how deep a search is allowed to run, whichever ends first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: u8,
+    pub time: Option<Duration>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            depth: 8,
+            time: None,
+        }
+    }
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> SearchLimits {
+        SearchLimits { depth, time: None }
+    }
+
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits {
+            depth: 60,
+            time: Some(time),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    // None once the game is over
+    pub best: Option<Action>,
+    // Score from the side to move's point of view
+    pub score: i32,
+    // Deepest fully completed iteration
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+struct Searcher<'a, E: Evaluator + ?Sized> {
+    table: &'a mut TranspositionTable,
+    evaluator: &'a E,
+    nodes: u64,
+    deadline: Option<Instant>,
+    // Nodes since the clock was last looked at, leaves included
+    unchecked: u32,
+    aborted: bool,
+}
+
+impl<E: Evaluator + ?Sized> Searcher<'_, E> {
+    fn out_of_time(&mut self) -> bool {
+        if !self.aborted && self.unchecked >= CHECK_INTERVAL {
+            self.unchecked = 0;
+            if let Some(deadline) = self.deadline {
+                self.aborted = Instant::now() >= deadline;
+            }
+        }
+        self.aborted
+    }
+
+    fn negamax(&mut self, ally: u64, foe: u64, depth: u8, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        self.unchecked += 1;
+        let moves = legal_moves(ally, foe);
+        if moves == 0 {
+            if legal_moves(foe, ally) == 0 {
+                return final_score(ally, foe);
+            }
+            return -self.negamax(foe, ally, depth, -beta, -alpha);
+        }
+        if depth == 0 {
+            return self.evaluator.evaluate(ally, foe);
+        }
+        if self.out_of_time() {
+            return alpha;
+        }
+
+        let key = hash_bitboards(ally, foe);
+        let mut hash_move = 0;
+        if let Some(entry) = self.table.probe(key) {
+            hash_move = entry.best;
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => {}
+                }
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = 0;
+        for position in ordered_moves(ally, foe, moves, hash_move) {
+            let flipped = flips(ally, foe, position);
+            let score = -self.negamax(
+                foe & !flipped,
+                ally | flipped | position,
+                depth - 1,
+                -beta,
+                -alpha,
+            );
+            if self.aborted {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = position;
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(key, depth, bound, best, best_move);
+        best
+    }
+
+    // Best placement and its score, None if the search ran out of time
+    fn root(&mut self, ally: u64, foe: u64, depth: u8, first: u64) -> Option<(u64, i32)> {
+        let mut alpha = -INFINITY;
+        let mut best = None;
+        for position in ordered_moves(ally, foe, legal_moves(ally, foe), first) {
+            let flipped = flips(ally, foe, position);
+            let score = -self.negamax(
+                foe & !flipped,
+                ally | flipped | position,
+                depth - 1,
+                -INFINITY,
+                -alpha,
+            );
+            if self.aborted {
+                return None;
+            }
+            if best.is_none() || score > alpha {
+                alpha = score;
+                best = Some((position, score));
+            }
+        }
+        best
+    }
+}
+
+// Disc differential with the win bonus for a finished game
+fn final_score(ally: u64, foe: u64) -> i32 {
+    let difference = ally.count_ones() as i32 - foe.count_ones() as i32;
+    difference.signum() * WIN_SCORE + difference
+}
+
+// Moves tried first are the given one, then those leaving the foe fewest replies
+fn ordered_moves(ally: u64, foe: u64, mut moves: u64, first: u64) -> Vec<u64> {
+    let mut ordered = Vec::with_capacity(moves.count_ones() as usize);
+    while moves != 0 {
+        let position = moves & moves.wrapping_neg();
+        moves &= moves - 1;
+        let flipped = flips(ally, foe, position);
+        let replies = legal_moves(foe & !flipped, ally | flipped | position).count_ones() as i32;
+        let key = if position == first {
+            i32::MIN
+        } else if position & CORNERS != 0 {
+            replies - 64
+        } else {
+            replies
+        };
+        ordered.push((key, position));
+    }
+    ordered.sort_unstable_by_key(|&(key, _)| key);
+    ordered.into_iter().map(|(_, position)| position).collect()
+}
+
+// Iterative deepening alpha-beta search from the side to move
+pub fn search(state: &GameState, limits: &SearchLimits) -> SearchResult {
+    search_with_table(state, limits, &mut TranspositionTable::default())
+}
+
+// Search reusing a table kept between calls, such as one per game
+pub fn search_with_table(
+    state: &GameState,
+    limits: &SearchLimits,
+    table: &mut TranspositionTable,
+) -> SearchResult {
+    search_with_evaluator(state, limits, table, &PatternEvaluator::default())
+}
+
+// Search scoring unfinished positions with the given evaluator
+pub fn search_with_evaluator<E: Evaluator + ?Sized>(
+    state: &GameState,
+    limits: &SearchLimits,
+    table: &mut TranspositionTable,
+    evaluator: &E,
+) -> SearchResult {
+    let (ally, foe) = state.config.ally_foe();
+    table.new_search();
+    let mut searcher = Searcher {
+        table,
+        evaluator,
+        nodes: 0,
+        deadline: limits.time.map(|time| Instant::now() + time),
+        unchecked: 0,
+        aborted: false,
+    };
+
+    if state.is_terminal() {
+        return SearchResult {
+            best: None,
+            score: final_score(ally, foe),
+            depth: 0,
+            nodes: 0,
+        };
+    }
+    if state.must_pass() {
+        let score = -searcher.negamax(foe, ally, limits.depth.max(1), -INFINITY, INFINITY);
+        return SearchResult {
+            best: Some(Action::Pass),
+            score,
+            depth: limits.depth.max(1),
+            nodes: searcher.nodes,
+        };
+    }
+
+    let moves = legal_moves(ally, foe);
+    let mut result = SearchResult {
+        best: Some(Action::Place(ordered_moves(ally, foe, moves, 0)[0])),
+        score: 0,
+        depth: 0,
+        nodes: 0,
+    };
+    let mut first = 0;
+    for depth in 1..=limits.depth.max(1) {
+        match searcher.root(ally, foe, depth, first) {
+            Some((position, score)) => {
+                first = position;
+                result.best = Some(Action::Place(position));
+                result.score = score;
+                result.depth = depth;
+            }
+            None => break,
+        }
+        // Nothing deeper to find once every line reaches the end of the game
+        if depth as u32 >= (!(ally | foe)).count_ones() {
+            break;
+        }
+    }
+    result.nodes = searcher.nodes;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard_position;
+    use crate::game::PieceConfig;
+
+    fn evaluate(ally: u64, foe: u64) -> i32 {
+        PatternEvaluator::default().evaluate(ally, foe)
+    }
+
+    // Plain minimax without pruning or ordering
+    fn minimax(ally: u64, foe: u64, depth: u8) -> i32 {
+        let mut moves = legal_moves(ally, foe);
+        if moves == 0 {
+            if legal_moves(foe, ally) == 0 {
+                return final_score(ally, foe);
+            }
+            return -minimax(foe, ally, depth);
+        }
+        if depth == 0 {
+            return evaluate(ally, foe);
+        }
+        let mut best = -INFINITY;
+        while moves != 0 {
+            let position = moves & moves.wrapping_neg();
+            moves &= moves - 1;
+            let flipped = flips(ally, foe, position);
+            best = best.max(-minimax(
+                foe & !flipped,
+                ally | flipped | position,
+                depth - 1,
+            ));
+        }
+        best
+    }
+
+    #[test]
+    fn test_matches_minimax() {
+        let mut state = GameState::new();
+        for _ in 0..12 {
+            let (ally, foe) = state.config.ally_foe();
+            let result = search(&state, &SearchLimits::depth(4));
+            assert_eq!(result.score, minimax(ally, foe, 4));
+            state = state.apply(result.best.unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_takes_winning_move() {
+        // Black takes the last white piece and wins outright
+        let state = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0),
+            white_pieces: bitboard_position(0, 1) | bitboard_position(5, 5),
+            blacks_play: true,
+        });
+        let result = search(&state, &SearchLimits::depth(1));
+        assert_eq!(result.best, Some(Action::Place(bitboard_position(0, 2))));
+    }
+
+    #[test]
+    fn test_terminal_and_pass() {
+        let finished = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0),
+            white_pieces: bitboard_position(7, 7),
+            blacks_play: true,
+        });
+        assert_eq!(search(&finished, &SearchLimits::default()).best, None);
+
+        let stuck = GameState::from(PieceConfig {
+            black_pieces: bitboard_position(0, 0),
+            white_pieces: bitboard_position(0, 1),
+            blacks_play: false,
+        });
+        let result = search(&stuck, &SearchLimits::depth(2));
+        assert_eq!(result.best, Some(Action::Pass));
+        assert!(result.score < 0);
+    }
+
+    #[test]
+    fn test_clock_counts_leaves() {
+        let mut table = TranspositionTable::default();
+        let evaluator = PatternEvaluator::default();
+        let mut searcher = Searcher {
+            table: &mut table,
+            evaluator: &evaluator,
+            nodes: 0,
+            deadline: Some(Instant::now()),
+            unchecked: CHECK_INTERVAL - 2,
+            aborted: false,
+        };
+        let (ally, foe) = GameState::new().config.ally_foe();
+        // Only the leaves of the first search reach the interval
+        searcher.negamax(ally, foe, 1, -INFINITY, INFINITY);
+        assert!(!searcher.aborted);
+        searcher.negamax(ally, foe, 1, -INFINITY, INFINITY);
+        assert!(searcher.aborted);
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let limits = SearchLimits::time(Duration::from_millis(50));
+        let start = Instant::now();
+        let result = search(&GameState::new(), &limits);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(matches!(result.best, Some(Action::Place(_))));
+    }
+}
@@ -0,0 +1,798 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use super::{Match, Recipient};
+use crate::clock::TimeControl;
+use crate::game::Side;
+use crate::protocol::{GameInfo, Message};
+
+pub type ClientId = u64;
+pub type GameId = u32;
+
+const NAME: &str = concat!("reversi server ", env!("CARGO_PKG_VERSION"));
+
+// How long a player who dropped out of a game has to resume it
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(60);
+
+// A game with its seats, it starts once both are taken
+#[derive(Clone, Debug)]
+struct Table {
+    host: ClientId,
+    host_side: Side,
+    guest: Option<ClientId>,
+    spectators: Vec<ClientId>,
+    game: Match,
+}
+
+impl Table {
+    fn player(&self, side: Side) -> Option<ClientId> {
+        if side == self.host_side {
+            Some(self.host)
+        } else {
+            self.guest
+        }
+    }
+
+    fn side_of(&self, client: ClientId) -> Side {
+        if client == self.host {
+            self.host_side
+        } else {
+            self.host_side.opponent()
+        }
+    }
+
+    fn players(&self) -> impl Iterator<Item = ClientId> + '_ {
+        std::iter::once(self.host).chain(self.guest)
+    }
+
+    fn deliver(&self, replies: Vec<(Recipient, Message)>) -> Vec<(ClientId, Message)> {
+        let mut delivered = Vec::new();
+        for (recipient, reply) in replies {
+            for client in self.recipients(recipient) {
+                delivered.push((client, reply.clone()));
+            }
+        }
+        delivered
+    }
+
+    fn recipients(&self, recipient: Recipient) -> Vec<ClientId> {
+        let everyone = self.players().chain(self.spectators.iter().copied());
+        match recipient {
+            Recipient::Player(side) => self.player(side).into_iter().collect(),
+            Recipient::Watchers(side) => {
+                everyone.filter(|&c| Some(c) != self.player(side)).collect()
+            }
+            Recipient::Everyone => everyone.collect(),
+        }
+    }
+}
+
+// Every connected client and game on the server, transport agnostic so each
+// call only returns the messages to send
+#[derive(Clone, Debug)]
+pub struct Lobby {
+    names: HashMap<ClientId, String>,
+    seats: HashMap<ClientId, GameId>,
+    watching: HashMap<ClientId, GameId>,
+    tables: BTreeMap<GameId, Table>,
+    next_game: GameId,
+    grace: Duration,
+    tokens: HashMap<ClientId, u64>,
+    sessions: HashMap<u64, ClientId>,
+    // Players who dropped out of a running game and when their seat is lost
+    away: HashMap<ClientId, Instant>,
+    // Connections whose seat a resumed session took over, for the transport
+    // to close
+    replaced: Vec<ClientId>,
+    // Clock every new game is played with, untimed if None
+    time_control: Option<TimeControl>,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Lobby::new()
+    }
+}
+
+fn error(client: ClientId, text: &str) -> Vec<(ClientId, Message)> {
+    vec![(client, Message::Error(text.to_string()))]
+}
+
+impl Lobby {
+    pub fn new() -> Lobby {
+        Lobby {
+            names: HashMap::new(),
+            seats: HashMap::new(),
+            watching: HashMap::new(),
+            tables: BTreeMap::new(),
+            next_game: 0,
+            grace: DEFAULT_GRACE,
+            tokens: HashMap::new(),
+            sessions: HashMap::new(),
+            away: HashMap::new(),
+            replaced: Vec::new(),
+            time_control: None,
+        }
+    }
+
+    pub fn with_grace(self, grace: Duration) -> Lobby {
+        Lobby { grace, ..self }
+    }
+
+    pub fn with_time_control(self, time_control: TimeControl) -> Lobby {
+        Lobby {
+            time_control: Some(time_control),
+            ..self
+        }
+    }
+
+    // Games waiting for a second player and the ones being played
+    pub fn games(&self) -> Vec<GameInfo> {
+        self.tables
+            .iter()
+            .map(|(&id, table)| GameInfo {
+                id,
+                host: self.names[&table.host].clone(),
+                side: table.host_side,
+                guest: table.guest.map(|guest| self.names[&guest].clone()),
+            })
+            .collect()
+    }
+
+    // Games with both seats taken
+    pub fn running_games(&self) -> usize {
+        self.tables
+            .values()
+            .filter(|table| table.guest.is_some())
+            .count()
+    }
+
+    fn create(&mut self, client: ClientId, side: Side) -> Vec<(ClientId, Message)> {
+        let id = self.next_game;
+        self.next_game += 1;
+        self.tables.insert(
+            id,
+            Table {
+                host: client,
+                host_side: side,
+                guest: None,
+                spectators: Vec::new(),
+                game: self.time_control.map_or_else(Match::new, Match::with_clock),
+            },
+        );
+        self.seats.insert(client, id);
+        vec![(client, Message::GameCreated(id))]
+    }
+
+    fn join(&mut self, client: ClientId, id: GameId, now: Instant) -> Vec<(ClientId, Message)> {
+        let Some(table) = self.tables.get_mut(&id) else {
+            return error(client, "no such game");
+        };
+        if table.guest.is_some() {
+            return error(client, "game is full");
+        }
+        table.guest = Some(client);
+        self.seats.insert(client, id);
+        let mut replies = vec![
+            (table.host, Message::ColorAssigned(table.host_side)),
+            (client, Message::ColorAssigned(table.host_side.opponent())),
+        ];
+        let clock = table.game.start_clock(now);
+        replies.extend(table.deliver(clock));
+        replies
+    }
+
+    // Passes a game message on to the match the client is seated at, the
+    // players return to the lobby once it ends
+    fn play(
+        &mut self,
+        client: ClientId,
+        message: Message,
+        now: Instant,
+    ) -> Vec<(ClientId, Message)> {
+        let Some(&id) = self.seats.get(&client) else {
+            return error(client, "not in a game");
+        };
+        let table = self.tables.get_mut(&id).unwrap();
+        if table.guest.is_none() {
+            return error(client, "waiting for an opponent");
+        }
+        if table
+            .players()
+            .any(|player| self.away.contains_key(&player))
+        {
+            return error(client, "game is paused");
+        }
+        let side = table.side_of(client);
+        let replies = table.game.handle(side, message, now);
+        let replies = table.deliver(replies);
+        if table.game.is_finished() {
+            self.close(id);
+        }
+        replies
+    }
+
+    // Shows the client the current position and every move after it
+    fn spectate(&mut self, client: ClientId, id: GameId, now: Instant) -> Vec<(ClientId, Message)> {
+        if !self.tables.contains_key(&id) {
+            return error(client, "no such game");
+        }
+        self.unwatch(client);
+        let table = self.tables.get_mut(&id).unwrap();
+        table.spectators.push(client);
+        self.watching.insert(client, id);
+        let position = Message::Position(table.game.state().config);
+        let clock = table.game.clock(now);
+        std::iter::once(position)
+            .chain(clock)
+            .map(|message| (client, message))
+            .collect()
+    }
+
+    fn unwatch(&mut self, client: ClientId) {
+        if let Some(id) = self.watching.remove(&client) {
+            let table = self.tables.get_mut(&id).unwrap();
+            table.spectators.retain(|&spectator| spectator != client);
+        }
+    }
+
+    fn close(&mut self, id: GameId) -> Option<Table> {
+        let table = self.tables.remove(&id)?;
+        for player in table.players() {
+            self.seats.remove(&player);
+        }
+        for spectator in &table.spectators {
+            self.watching.remove(spectator);
+        }
+        Some(table)
+    }
+
+    // Hands the seat of a player who dropped out over to the new connection
+    // and sends it the game so far. The old connection may not have been
+    // seen to drop yet, it loses the seat and is closed
+    fn resume(&mut self, client: ClientId, token: u64, now: Instant) -> Vec<(ClientId, Message)> {
+        let Some(&previous) = self.sessions.get(&token) else {
+            return error(client, "no game to resume");
+        };
+        let running = self
+            .seats
+            .get(&previous)
+            .is_some_and(|id| self.tables[id].guest.is_some());
+        if !running {
+            return error(client, "no game to resume");
+        }
+        let away = self.away.remove(&previous).is_some();
+        let mut replies = Vec::new();
+        if !away {
+            replies.push((
+                previous,
+                Message::Error("session resumed elsewhere".to_string()),
+            ));
+            self.replaced.push(previous);
+        }
+        let name = self.names.remove(&previous).unwrap();
+        self.names.insert(client, name);
+        self.tokens.remove(&previous);
+        self.tokens.insert(client, token);
+        self.sessions.insert(token, client);
+        let id = self.seats.remove(&previous).unwrap();
+        self.seats.insert(client, id);
+
+        let table = self.tables.get_mut(&id).unwrap();
+        if table.host == previous {
+            table.host = client;
+        } else {
+            table.guest = Some(client);
+        }
+        let side = table.side_of(client);
+        replies.push((
+            client,
+            Message::History {
+                side,
+                moves: table.game.moves().to_vec(),
+            },
+        ));
+        // The game only stopped if the player was seen to drop
+        if !away {
+            replies.extend(table.game.clock(now).map(|clock| (client, clock)));
+        } else if !table
+            .players()
+            .any(|player| self.away.contains_key(&player))
+        {
+            for watcher in table.recipients(Recipient::Watchers(side)) {
+                replies.push((watcher, Message::Unpaused));
+            }
+            let clock = table.game.start_clock(now);
+            replies.extend(table.deliver(clock));
+        }
+        replies
+    }
+
+    // Connections replaced by a resumed session since the last call
+    pub fn replaced(&mut self) -> Vec<ClientId> {
+        std::mem::take(&mut self.replaced)
+    }
+
+    // Unguessable token that no other session holds
+    fn new_token(&self) -> u64 {
+        loop {
+            let mut bytes = [0; 8];
+            getrandom::getrandom(&mut bytes).expect("no random source for session tokens");
+            let token = u64::from_le_bytes(bytes);
+            if !self.sessions.contains_key(&token) {
+                return token;
+            }
+        }
+    }
+
+    fn forget(&mut self, client: ClientId) {
+        self.names.remove(&client);
+        if let Some(token) = self.tokens.remove(&client) {
+            self.sessions.remove(&token);
+        }
+    }
+
+    // Handles a message from a client, returning what to send to whom
+    pub fn handle(
+        &mut self,
+        client: ClientId,
+        message: Message,
+        now: Instant,
+    ) -> Vec<(ClientId, Message)> {
+        match message {
+            Message::Hello { name } => {
+                self.names.insert(client, name);
+                let token = self.new_token();
+                // Saying hello again replaces the session
+                if let Some(previous) = self.tokens.insert(client, token) {
+                    self.sessions.remove(&previous);
+                }
+                self.sessions.insert(token, client);
+                let name = NAME.to_string();
+                return vec![
+                    (client, Message::Hello { name }),
+                    (client, Message::Session(token)),
+                ];
+            }
+            Message::Resume(token) if !self.names.contains_key(&client) => {
+                return self.resume(client, token, now);
+            }
+            _ => {}
+        }
+        if !self.names.contains_key(&client) {
+            return error(client, "expected hello");
+        }
+        match message {
+            Message::ListGames => vec![(client, Message::GameList(self.games()))],
+            Message::CreateGame(_) | Message::JoinGame(_) if self.seats.contains_key(&client) => {
+                error(client, "already in a game")
+            }
+            Message::Spectate(_) if self.seats.contains_key(&client) => {
+                error(client, "already in a game")
+            }
+            Message::CreateGame(side) => {
+                self.unwatch(client);
+                self.create(client, side)
+            }
+            Message::JoinGame(id) => {
+                self.unwatch(client);
+                self.join(client, id, now)
+            }
+            Message::Spectate(id) => self.spectate(client, id, now),
+            Message::Move(_) | Message::Pass | Message::Resign => self.play(client, message, now),
+            Message::Ping(_) => Vec::new(),
+            message => error(client, &format!("unexpected {message:?}")),
+        }
+    }
+
+    // Players of a running game keep their seat for the grace period while
+    // everyone else watching is told the game is paused, the clocks stop
+    // until they are back
+    pub fn disconnect(&mut self, client: ClientId, now: Instant) -> Vec<(ClientId, Message)> {
+        self.unwatch(client);
+        if let Some(table) = self
+            .seats
+            .get(&client)
+            .map(|id| self.tables.get_mut(id).unwrap())
+        {
+            if table.guest.is_some() {
+                table.game.pause_clock(now);
+                self.away.insert(client, now + self.grace);
+                let seconds = self.grace.as_secs() as u32;
+                let side = table.side_of(client);
+                return table
+                    .recipients(Recipient::Watchers(side))
+                    .into_iter()
+                    .filter(|watcher| !self.away.contains_key(watcher))
+                    .map(|watcher| (watcher, Message::Paused { seconds }))
+                    .collect();
+            }
+        }
+        self.abandon(client)
+    }
+
+    // Gives up the seats of players whose grace period ran out and ends the
+    // games where the side to move ran out of time
+    pub fn expire(&mut self, now: Instant) -> Vec<(ClientId, Message)> {
+        let expired: Vec<ClientId> = self
+            .away
+            .iter()
+            .filter(|(_, &deadline)| deadline <= now)
+            .map(|(&client, _)| client)
+            .collect();
+        let mut replies = Vec::new();
+        for client in expired {
+            if self.away.remove(&client).is_some() {
+                replies.extend(self.abandon(client));
+            }
+        }
+        let mut finished = Vec::new();
+        for (&id, table) in &mut self.tables {
+            let flagged = table.game.check_time(now);
+            replies.extend(table.deliver(flagged));
+            if table.game.is_finished() {
+                finished.push(id);
+            }
+        }
+        for id in finished {
+            self.close(id);
+        }
+        replies
+    }
+
+    // Forgets the client, an opponent left behind is told and sent back to
+    // the lobby along with any spectators
+    fn abandon(&mut self, client: ClientId) -> Vec<(ClientId, Message)> {
+        self.forget(client);
+        let Some(&id) = self.seats.get(&client) else {
+            return Vec::new();
+        };
+        let table = self.close(id).unwrap();
+        let mut replies = Vec::new();
+        for player in table.players().filter(|&player| player != client) {
+            // An opponent who is away too has nothing left to come back to
+            if self.away.remove(&player).is_some() {
+                self.forget(player);
+            } else {
+                replies.push((player, Message::Error("opponent disconnected".to_string())));
+            }
+        }
+        for &spectator in &table.spectators {
+            replies.push((spectator, Message::Error("player disconnected".to_string())));
+        }
+        replies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Remaining;
+    use crate::game::{Action, GameState};
+    use crate::notation::parse_square;
+
+    fn lobby(clients: &[ClientId]) -> Lobby {
+        let mut lobby = Lobby::new();
+        for &client in clients {
+            let name = format!("player {client}");
+            lobby.handle(client, Message::Hello { name }, Instant::now());
+        }
+        lobby
+    }
+
+    fn square(name: &str) -> Message {
+        Message::Move(parse_square(name).unwrap())
+    }
+
+    #[test]
+    fn test_create_list_and_join() {
+        let now = Instant::now();
+        let mut lobby = lobby(&[1, 2, 3]);
+        assert_eq!(
+            lobby.handle(1, Message::CreateGame(Side::White), now),
+            vec![(1, Message::GameCreated(0))]
+        );
+        assert_eq!(
+            lobby.handle(3, Message::ListGames, now),
+            vec![(
+                3,
+                Message::GameList(vec![GameInfo {
+                    id: 0,
+                    host: "player 1".to_string(),
+                    side: Side::White,
+                    guest: None,
+                }])
+            )]
+        );
+        assert_eq!(
+            lobby.handle(2, Message::JoinGame(0), now),
+            vec![
+                (1, Message::ColorAssigned(Side::White)),
+                (2, Message::ColorAssigned(Side::Black)),
+            ]
+        );
+        assert_eq!(lobby.games()[0].guest, Some("player 2".to_string()));
+        assert_eq!(lobby.running_games(), 1);
+        assert!(matches!(
+            lobby.handle(3, Message::JoinGame(0), now).as_slice(),
+            [(3, Message::Error(_))]
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_games() {
+        let now = Instant::now();
+        let mut lobby = lobby(&[1, 2, 3, 4]);
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        lobby.handle(3, Message::CreateGame(Side::Black), now);
+        lobby.handle(2, Message::JoinGame(0), now);
+        lobby.handle(4, Message::JoinGame(1), now);
+        assert_eq!(lobby.handle(1, square("f5"), now), vec![(2, square("f5"))]);
+        assert_eq!(lobby.handle(3, square("d3"), now), vec![(4, square("d3"))]);
+        assert!(matches!(
+            lobby.handle(4, square("f5"), now).as_slice(),
+            [(4, Message::Error(_))]
+        ));
+    }
+
+    #[test]
+    fn test_rules() {
+        let now = Instant::now();
+        let mut lobby = lobby(&[1, 2]);
+        assert!(matches!(
+            lobby.handle(1, square("f5"), now).as_slice(),
+            [(1, Message::Error(_))]
+        ));
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        assert!(matches!(
+            lobby
+                .handle(1, Message::CreateGame(Side::Black), now)
+                .as_slice(),
+            [(1, Message::Error(_))]
+        ));
+        assert!(matches!(
+            lobby.handle(1, square("f5"), now).as_slice(),
+            [(1, Message::Error(_))]
+        ));
+        assert!(matches!(
+            lobby.handle(7, Message::ListGames, now).as_slice(),
+            [(7, Message::Error(_))]
+        ));
+    }
+
+    #[test]
+    fn test_new_game_after_end() {
+        let now = Instant::now();
+        let mut lobby = lobby(&[1, 2]);
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        lobby.handle(2, Message::JoinGame(0), now);
+        let replies = lobby.handle(2, Message::Resign, now);
+        assert!(replies.contains(&(1, Message::Resign)));
+        assert_eq!(lobby.running_games(), 0);
+        assert_eq!(
+            lobby.handle(2, Message::CreateGame(Side::Black), now),
+            vec![(2, Message::GameCreated(1))]
+        );
+    }
+
+    fn token(replies: &[(ClientId, Message)]) -> u64 {
+        match replies {
+            [_, (_, Message::Session(token))] => *token,
+            _ => panic!("no session in {replies:?}"),
+        }
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let now = Instant::now();
+        let mut lobby = lobby(&[1, 2]);
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        lobby.handle(2, Message::JoinGame(0), now);
+        assert_eq!(
+            lobby.disconnect(1, now),
+            vec![(2, Message::Paused { seconds: 60 })]
+        );
+        assert!(matches!(
+            lobby.handle(2, square("f5"), now).as_slice(),
+            [(2, Message::Error(_))]
+        ));
+        assert!(lobby.expire(now + DEFAULT_GRACE / 2).is_empty());
+        assert_eq!(
+            lobby.expire(now + DEFAULT_GRACE),
+            vec![(2, Message::Error("opponent disconnected".to_string()))]
+        );
+        assert_eq!(lobby.running_games(), 0);
+        assert_eq!(
+            lobby.handle(2, Message::CreateGame(Side::White), now),
+            vec![(2, Message::GameCreated(1))]
+        );
+        // Hosts leaving close their open game right away
+        assert!(lobby.disconnect(2, now).is_empty());
+        assert!(lobby.games().is_empty());
+    }
+
+    #[test]
+    fn test_resume() {
+        let now = Instant::now();
+        let mut lobby = Lobby::new().with_grace(Duration::from_secs(5));
+        let name = "alice".to_string();
+        let session = token(&lobby.handle(1, Message::Hello { name }, now));
+        let name = "bob".to_string();
+        lobby.handle(2, Message::Hello { name }, now);
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        lobby.handle(2, Message::JoinGame(0), now);
+        lobby.handle(1, square("f5"), now);
+
+        assert_eq!(
+            lobby.disconnect(1, now),
+            vec![(2, Message::Paused { seconds: 5 })]
+        );
+        assert!(matches!(
+            lobby
+                .handle(3, Message::Resume(session ^ 1), now)
+                .as_slice(),
+            [(3, Message::Error(_))]
+        ));
+        let moves = vec![Action::Place(parse_square("f5").unwrap())];
+        assert_eq!(
+            lobby.handle(3, Message::Resume(session), now),
+            vec![
+                (
+                    3,
+                    Message::History {
+                        side: Side::Black,
+                        moves
+                    }
+                ),
+                (2, Message::Unpaused),
+            ]
+        );
+        assert_eq!(lobby.games()[0].host, "alice");
+        assert_eq!(lobby.handle(2, square("d6"), now), vec![(3, square("d6"))]);
+        assert!(lobby.expire(now + Duration::from_secs(10)).is_empty());
+        // Sessions of players back in the lobby have no game to resume
+        lobby.handle(3, Message::Resign, now);
+        assert!(matches!(
+            lobby.handle(4, Message::Resume(session), now).as_slice(),
+            [(4, Message::Error(_))]
+        ));
+    }
+
+    #[test]
+    fn test_resume_connected() {
+        let now = Instant::now();
+        let mut lobby = Lobby::new();
+        let name = "alice".to_string();
+        let session = token(&lobby.handle(1, Message::Hello { name }, now));
+        let name = "bob".to_string();
+        lobby.handle(2, Message::Hello { name }, now);
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        lobby.handle(2, Message::JoinGame(0), now);
+        lobby.handle(1, square("f5"), now);
+
+        // Client 1 dropped without the server noticing yet
+        let moves = vec![Action::Place(parse_square("f5").unwrap())];
+        assert_eq!(
+            lobby.handle(3, Message::Resume(session), now),
+            vec![
+                (1, Message::Error("session resumed elsewhere".to_string())),
+                (
+                    3,
+                    Message::History {
+                        side: Side::Black,
+                        moves
+                    }
+                ),
+            ]
+        );
+        assert_eq!(lobby.replaced(), vec![1]);
+        assert!(lobby.replaced().is_empty());
+        assert_eq!(lobby.handle(2, square("d6"), now), vec![(3, square("d6"))]);
+        // The old connection closing later leaves the game alone
+        assert!(lobby.disconnect(1, now).is_empty());
+        assert_eq!(lobby.running_games(), 1);
+        assert_eq!(lobby.games()[0].host, "alice");
+    }
+
+    #[test]
+    fn test_tokens() {
+        let now = Instant::now();
+        let mut lobby = Lobby::new();
+        let mut tokens = Vec::new();
+        for client in 0..100 {
+            let name = format!("player {client}");
+            tokens.push(token(&lobby.handle(client, Message::Hello { name }, now)));
+        }
+        tokens.sort();
+        tokens.dedup();
+        assert_eq!(tokens.len(), 100);
+        // A second hello drops the first session
+        let name = "again".to_string();
+        let again = token(&lobby.handle(0, Message::Hello { name }, now));
+        assert_eq!(lobby.sessions.len(), 100);
+        assert_eq!(lobby.sessions[&again], 0);
+    }
+
+    #[test]
+    fn test_spectators() {
+        let now = Instant::now();
+        let mut lobby = lobby(&[1, 2, 3]);
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        lobby.handle(2, Message::JoinGame(0), now);
+        lobby.handle(1, square("f5"), now);
+        let position = GameState::new().apply(Action::Place(parse_square("f5").unwrap()));
+        assert_eq!(
+            lobby.handle(3, Message::Spectate(0), now),
+            vec![(3, Message::Position(position.unwrap().config))]
+        );
+        assert_eq!(
+            lobby.handle(2, square("d6"), now),
+            vec![(1, square("d6")), (3, square("d6"))]
+        );
+        // Spectators cannot play and players cannot spectate
+        assert!(matches!(
+            lobby.handle(3, square("c3"), now).as_slice(),
+            [(3, Message::Error(_))]
+        ));
+        assert!(matches!(
+            lobby.handle(1, Message::Spectate(0), now).as_slice(),
+            [(1, Message::Error(_))]
+        ));
+        assert_eq!(
+            lobby.disconnect(2, now),
+            vec![
+                (1, Message::Paused { seconds: 60 }),
+                (3, Message::Paused { seconds: 60 }),
+            ]
+        );
+        assert_eq!(
+            lobby.expire(now + DEFAULT_GRACE),
+            vec![
+                (1, Message::Error("opponent disconnected".to_string())),
+                (3, Message::Error("player disconnected".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_time_control() {
+        let now = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut lobby =
+            lobby(&[1, 2, 3]).with_time_control(TimeControl::SuddenDeath { total: minute });
+        lobby.handle(1, Message::CreateGame(Side::Black), now);
+        let clock = Message::Clock {
+            black: Remaining {
+                time: minute,
+                periods: 0,
+            },
+            white: Remaining {
+                time: minute,
+                periods: 0,
+            },
+            period: Duration::ZERO,
+        };
+        assert_eq!(
+            lobby.handle(2, Message::JoinGame(0), now),
+            vec![
+                (1, Message::ColorAssigned(Side::Black)),
+                (2, Message::ColorAssigned(Side::White)),
+                (1, clock.clone()),
+                (2, clock.clone()),
+            ]
+        );
+        assert_eq!(lobby.handle(3, Message::Spectate(0), now)[1], (3, clock));
+        // Time does not run while the game is paused
+        let seconds = |seconds| now + Duration::from_secs(seconds);
+        lobby.disconnect(1, seconds(10));
+        assert!(lobby.expire(seconds(40)).is_empty());
+        let token = lobby.tokens[&1];
+        lobby.handle(4, Message::Resume(token), seconds(40));
+        assert!(lobby.expire(seconds(89)).is_empty());
+        let replies = lobby.expire(seconds(90));
+        assert!(replies.contains(&(3, Message::TimeOut(Side::Black))));
+        assert_eq!(lobby.running_games(), 0);
+    }
+}
@@ -0,0 +1,389 @@
+use std::time::Instant;
+
+use crate::clock::{Clock, TimeControl};
+use crate::game::{Action, GameState, Side};
+use crate::notation::square_name;
+use crate::protocol::Message;
+
+pub mod lobby;
+
+pub use lobby::{ClientId, GameId, Lobby};
+
+// Who a message produced by the server should be sent to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recipient {
+    Player(Side),
+    // Everyone following the game apart from the given player
+    Watchers(Side),
+    Everyone,
+}
+
+// Authoritative state of a networked game, the clients only ever see moves
+// the server accepted
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Match {
+    state: GameState,
+    moves: Vec<Action>,
+    finished: bool,
+    // Untimed games have no clock
+    clock: Option<Clock>,
+}
+
+impl Match {
+    pub fn new() -> Match {
+        Match::default()
+    }
+
+    pub fn with_clock(control: TimeControl) -> Match {
+        Match {
+            clock: Some(Clock::new(control)),
+            ..Match::default()
+        }
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    // Every accepted action including the passes the server played
+    pub fn moves(&self) -> &[Action] {
+        &self.moves
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Both clocks as of now, None for untimed games
+    pub fn clock(&self, now: Instant) -> Option<Message> {
+        let clock = self.clock.as_ref()?;
+        Some(Message::Clock {
+            black: clock.remaining(Side::Black, now),
+            white: clock.remaining(Side::White, now),
+            period: clock.period(),
+        })
+    }
+
+    fn clock_update(&self, now: Instant) -> Vec<(Recipient, Message)> {
+        self.clock(now)
+            .map(|clock| (Recipient::Everyone, clock))
+            .into_iter()
+            .collect()
+    }
+
+    // Runs the clock of the side to move, when the game starts or goes on
+    // after a pause
+    pub fn start_clock(&mut self, now: Instant) -> Vec<(Recipient, Message)> {
+        if self.finished {
+            return Vec::new();
+        }
+        let side = self.state.side_to_move();
+        if let Some(clock) = &mut self.clock {
+            clock.start(side, now);
+        }
+        self.clock_update(now)
+    }
+
+    pub fn pause_clock(&mut self, now: Instant) {
+        if let Some(clock) = &mut self.clock {
+            clock.pause(now);
+        }
+    }
+
+    // Ends the game once the side to move ran out of time
+    pub fn check_time(&mut self, now: Instant) -> Vec<(Recipient, Message)> {
+        if self.finished {
+            return Vec::new();
+        }
+        match self.clock.as_ref().and_then(|clock| clock.flagged(now)) {
+            Some(side) => self.time_out(side, now),
+            None => Vec::new(),
+        }
+    }
+
+    fn time_out(&mut self, side: Side, now: Instant) -> Vec<(Recipient, Message)> {
+        if let Some(clock) = &mut self.clock {
+            clock.stop(now);
+        }
+        let mut replies = self.clock_update(now);
+        replies.push((Recipient::Everyone, Message::TimeOut(side)));
+        replies.push(self.game_over());
+        replies
+    }
+
+    fn game_over(&mut self) -> (Recipient, Message) {
+        self.finished = true;
+        let (black, white) = self.state.score();
+        (
+            Recipient::Everyone,
+            Message::GameOver {
+                black: black as u8,
+                white: white as u8,
+            },
+        )
+    }
+
+    // Plays an action for the side to move and the passes it forces
+    fn play(&mut self, side: Side, action: Action, now: Instant) -> Vec<(Recipient, Message)> {
+        let error = |text: String| vec![(Recipient::Player(side), Message::Error(text))];
+        if side != self.state.side_to_move() {
+            return error("not your turn".to_string());
+        }
+        let Some(state) = self.state.apply(action) else {
+            return match action {
+                Action::Place(position) => error(format!("{} is not legal", square_name(position))),
+                Action::Pass => error("pass is not legal".to_string()),
+            };
+        };
+        // A move made after the flag fell loses on time instead
+        if let Some(clock) = &mut self.clock {
+            if !clock.stop(now) {
+                return self.time_out(side, now);
+            }
+        }
+        self.state = state;
+        self.moves.push(action);
+        let mut replies = vec![(
+            Recipient::Watchers(side),
+            match action {
+                Action::Place(position) => Message::Move(position),
+                Action::Pass => Message::Pass,
+            },
+        )];
+        if self.state.must_pass() {
+            self.state = self.state.apply(Action::Pass).unwrap();
+            self.moves.push(Action::Pass);
+            replies.push((Recipient::Everyone, Message::Pass));
+        }
+        if self.state.is_terminal() {
+            replies.push(self.game_over());
+        } else {
+            replies.extend(self.start_clock(now));
+        }
+        replies
+    }
+
+    // Handles a message from a player, returning what to send to whom
+    pub fn handle(
+        &mut self,
+        side: Side,
+        message: Message,
+        now: Instant,
+    ) -> Vec<(Recipient, Message)> {
+        if self.finished {
+            return vec![(
+                Recipient::Player(side),
+                Message::Error("game is over".to_string()),
+            )];
+        }
+        match message {
+            Message::Move(position) => self.play(side, Action::Place(position), now),
+            Message::Pass => self.play(side, Action::Pass, now),
+            Message::Resign => {
+                vec![
+                    (Recipient::Watchers(side), Message::Resign),
+                    self.game_over(),
+                ]
+            }
+            Message::Ping(_) => Vec::new(),
+            message => vec![(
+                Recipient::Player(side),
+                Message::Error(format!("unexpected {message:?}")),
+            )],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard_position;
+    use crate::clock::Remaining;
+    use crate::game::PieceConfig;
+    use crate::notation::{parse_square, parse_transcript};
+    use std::time::Duration;
+
+    fn square(name: &str) -> Message {
+        Message::Move(parse_square(name).unwrap())
+    }
+
+    fn is_error(replies: &[(Recipient, Message)], side: Side) -> bool {
+        matches!(replies, [(Recipient::Player(to), Message::Error(_))] if *to == side)
+    }
+
+    #[test]
+    fn test_relays_legal_moves() {
+        let now = Instant::now();
+        let mut game = Match::new();
+        let replies = game.handle(Side::Black, square("f5"), now);
+        assert_eq!(
+            replies,
+            vec![(Recipient::Watchers(Side::Black), square("f5"))]
+        );
+        assert_eq!(game.state().side_to_move(), Side::White);
+    }
+
+    #[test]
+    fn test_rejects_illegal_and_out_of_turn_moves() {
+        let now = Instant::now();
+        let mut game = Match::new();
+        assert!(is_error(
+            &game.handle(Side::White, square("f4"), now),
+            Side::White
+        ));
+        assert!(is_error(
+            &game.handle(Side::Black, square("a1"), now),
+            Side::Black
+        ));
+        assert!(is_error(
+            &game.handle(Side::Black, Message::Pass, now),
+            Side::Black
+        ));
+        assert!(is_error(
+            &game.handle(
+                Side::Black,
+                Message::GameOver {
+                    black: 64,
+                    white: 0
+                },
+                now
+            ),
+            Side::Black
+        ));
+        assert_eq!(game, Match::new());
+    }
+
+    #[test]
+    fn test_game_over() {
+        let now = Instant::now();
+        let mut game = Match::new();
+        let mut replies = Vec::new();
+        for action in parse_transcript("f5d6c5f4e3f6g5e6e7").unwrap() {
+            let Action::Place(position) = action else {
+                unreachable!()
+            };
+            let side = game.state().side_to_move();
+            replies = game.handle(side, Message::Move(position), now);
+        }
+        assert!(game.is_finished());
+        assert_eq!(
+            replies.last(),
+            Some(&(
+                Recipient::Everyone,
+                Message::GameOver {
+                    black: 13,
+                    white: 0
+                }
+            ))
+        );
+        assert!(is_error(
+            &game.handle(Side::White, square("a1"), now),
+            Side::White
+        ));
+    }
+
+    #[test]
+    fn test_forced_pass() {
+        let now = Instant::now();
+        // After black takes c1 white is stuck, but black can still take g7
+        let mut game = Match {
+            state: GameState::from(PieceConfig {
+                black_pieces: bitboard_position(0, 0) | bitboard_position(7, 7),
+                white_pieces: bitboard_position(0, 1) | bitboard_position(6, 6),
+                blacks_play: true,
+            }),
+            ..Match::default()
+        };
+        let replies = game.handle(Side::Black, square("c1"), now);
+        assert_eq!(replies[1], (Recipient::Everyone, Message::Pass));
+        assert_eq!(game.state().side_to_move(), Side::Black);
+    }
+
+    #[test]
+    fn test_resign() {
+        let now = Instant::now();
+        let mut game = Match::new();
+        let replies = game.handle(Side::White, Message::Resign, now);
+        assert_eq!(
+            replies[0],
+            (Recipient::Watchers(Side::White), Message::Resign)
+        );
+        assert!(game.is_finished());
+    }
+
+    fn clocked() -> Match {
+        Match::with_clock(TimeControl::Fischer {
+            total: Duration::from_secs(60),
+            increment: Duration::from_secs(2),
+        })
+    }
+
+    fn remaining(seconds: u64) -> Remaining {
+        Remaining {
+            time: Duration::from_secs(seconds),
+            periods: 0,
+        }
+    }
+
+    #[test]
+    fn test_clock_broadcast() {
+        let now = Instant::now();
+        let mut game = clocked();
+        assert_eq!(
+            game.start_clock(now),
+            vec![(
+                Recipient::Everyone,
+                Message::Clock {
+                    black: remaining(60),
+                    white: remaining(60),
+                    period: Duration::ZERO
+                }
+            )]
+        );
+        let replies = game.handle(Side::Black, square("f5"), now + Duration::from_secs(10));
+        assert_eq!(
+            replies[1],
+            (
+                Recipient::Everyone,
+                Message::Clock {
+                    black: remaining(52),
+                    white: remaining(60),
+                    period: Duration::ZERO
+                }
+            )
+        );
+        // Nothing runs while the game is paused
+        game.pause_clock(now + Duration::from_secs(20));
+        assert!(game.check_time(now + Duration::from_secs(600)).is_empty());
+        game.start_clock(now + Duration::from_secs(600));
+        assert_eq!(
+            game.clock(now + Duration::from_secs(610)),
+            Some(Message::Clock {
+                black: remaining(52),
+                white: remaining(40),
+                period: Duration::ZERO
+            })
+        );
+    }
+
+    #[test]
+    fn test_loss_on_time() {
+        let now = Instant::now();
+        let mut game = clocked();
+        game.start_clock(now);
+        assert!(game.check_time(now + Duration::from_secs(59)).is_empty());
+        let replies = game.check_time(now + Duration::from_secs(60));
+        assert_eq!(
+            replies[1],
+            (Recipient::Everyone, Message::TimeOut(Side::Black))
+        );
+        assert!(game.is_finished());
+
+        // A move arriving after the flag fell loses as well
+        let mut game = clocked();
+        game.start_clock(now);
+        let replies = game.handle(Side::Black, square("f5"), now + Duration::from_secs(61));
+        assert!(replies.contains(&(Recipient::Everyone, Message::TimeOut(Side::Black))));
+        assert_eq!(game.moves(), &[]);
+    }
+}
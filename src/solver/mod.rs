@@ -0,0 +1,489 @@
+use crate::game::{Action, GameState};
+use crate::transposition::{hash_bitboards, Bound, TranspositionTable};
+use crate::{flips, legal_moves, stable_discs};
+
+// One more than the largest possible disc differential
+const BOUND: i32 = 65;
+// Below this many empty squares parity alone orders moves more cheaply
+const FASTEST_FIRST_EMPTIES: u32 = 7;
+// Below this many empty squares probing the table costs more than it saves
+const TABLE_EMPTIES: u32 = 7;
+// From this many empty squares the children are looked up in the table
+// before searching any of them
+const ETC_EMPTIES: u32 = 12;
+// Up to this many empty squares they are tried directly, odd regions first,
+// instead of generating and ordering moves
+const FEW_EMPTIES: u32 = 5;
+// From this many empty squares moves are ordered by a shallow search instead
+// of the opponent's replies alone, one ply deeper every SHALLOW_STEP squares
+const SHALLOW_EMPTIES: u32 = 12;
+const SHALLOW_STEP: u32 = 4;
+
+const CORNERS: u64 = 0x8100000000000081;
+
+const QUADRANTS: [u64; 4] = [
+    0xF0F0F0F000000000,
+    0x0F0F0F0F00000000,
+    0x00000000F0F0F0F0,
+    0x000000000F0F0F0F,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolveMode {
+    // Exact final disc differential
+    Exact,
+    // Only whether the side to move wins, loses or draws
+    WinLossDraw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Solution {
+    // None once the game is over
+    pub best: Option<Action>,
+    // Final disc differential for the side to move with empty squares going to
+    // the winner, or -1, 0 and 1 for a loss, draw and win in WinLossDraw mode
+    pub score: i32,
+    pub nodes: u64,
+}
+
+// Disc differential at the end of the game, empty squares count for the winner
+fn final_score(ally: u64, foe: u64) -> i32 {
+    let difference = ally.count_ones() as i32 - foe.count_ones() as i32;
+    let empties = (!(ally | foe)).count_ones() as i32;
+    difference + difference.signum() * empties
+}
+
+// Score with a single empty square left, taken by whoever can play it
+fn last_square(ally: u64, foe: u64, position: u64) -> i32 {
+    let flipped = flips(ally, foe, position);
+    if flipped != 0 {
+        return final_score(ally | flipped | position, foe & !flipped);
+    }
+    let flipped = flips(foe, ally, position);
+    if flipped != 0 {
+        return final_score(ally & !flipped, foe | flipped | position);
+    }
+    final_score(ally, foe)
+}
+
+// Squares in quadrants holding an odd number of empty squares
+fn odd_regions(empty: u64) -> u64 {
+    QUADRANTS
+        .iter()
+        .filter(|&&quadrant| (empty & quadrant).count_ones() % 2 == 1)
+        .fold(0, |regions, quadrant| regions | quadrant)
+}
+
+// Moves and corners the side to move has over its opponent
+fn mobility(ally: u64, foe: u64) -> i32 {
+    let ours = legal_moves(ally, foe);
+    let theirs = legal_moves(foe, ally);
+    let count = |moves: u64, discs: u64| {
+        (moves.count_ones()
+            + 2 * (moves & CORNERS).count_ones()
+            + 4 * (discs & CORNERS).count_ones()) as i32
+    };
+    count(ours, ally) - count(theirs, foe)
+}
+
+// Alpha-beta on mobility alone, cheap enough to order moves by
+fn mobility_search(ally: u64, foe: u64, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+    let mut moves = legal_moves(ally, foe);
+    if depth == 0 || moves == 0 {
+        return mobility(ally, foe);
+    }
+    while moves != 0 {
+        let position = moves & moves.wrapping_neg();
+        moves &= moves - 1;
+        let flipped = flips(ally, foe, position);
+        let score = -mobility_search(
+            foe & !flipped,
+            ally | flipped | position,
+            depth - 1,
+            -beta,
+            -alpha,
+        );
+        if score > alpha {
+            alpha = score;
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    alpha
+}
+
+// Fills buffer with moves in search order, returning how many there are
+fn ordered_moves(ally: u64, foe: u64, mut moves: u64, buffer: &mut [u64; 64]) -> usize {
+    let empty = !(ally | foe);
+    let odd = odd_regions(empty);
+    let mut keys = [0; 64];
+    let empties = empty.count_ones();
+    let mut count = 0;
+    while moves != 0 {
+        let position = moves & moves.wrapping_neg();
+        moves &= moves - 1;
+        let parity = if position & odd != 0 { 0 } else { 1 };
+        let key = if empties >= SHALLOW_EMPTIES {
+            let flipped = flips(ally, foe, position);
+            let depth = (empties - SHALLOW_EMPTIES) / SHALLOW_STEP + 1;
+            mobility_search(
+                foe & !flipped,
+                ally | flipped | position,
+                depth,
+                -i32::MAX,
+                i32::MAX,
+            ) * 2
+                + parity
+        } else if empties >= FASTEST_FIRST_EMPTIES {
+            let flipped = flips(ally, foe, position);
+            let replies = legal_moves(foe & !flipped, ally | flipped | position);
+            // Replies taking a corner count double
+            let replies = replies.count_ones() + (replies & CORNERS).count_ones();
+            replies as i32 * 2 + parity
+        } else {
+            parity
+        };
+        // Insertion sort, move lists are short
+        let mut index = count;
+        while index > 0 && keys[index - 1] > key {
+            keys[index] = keys[index - 1];
+            buffer[index] = buffer[index - 1];
+            index -= 1;
+        }
+        keys[index] = key;
+        buffer[index] = position;
+        count += 1;
+    }
+    count
+}
+
+struct Solver<'a> {
+    table: &'a mut TranspositionTable,
+    nodes: u64,
+}
+
+impl Solver<'_> {
+    // Score of a child already known to be good enough to cut, so none of
+    // the moves needs searching
+    fn transposition_cutoff(&self, ally: u64, foe: u64, mut moves: u64, beta: i32) -> Option<i32> {
+        while moves != 0 {
+            let position = moves & moves.wrapping_neg();
+            moves &= moves - 1;
+            let flipped = flips(ally, foe, position);
+            let key = hash_bitboards(foe & !flipped, ally | flipped | position);
+            let Some(entry) = self.table.probe(key) else {
+                continue;
+            };
+            // The child scores at most its upper bound, so this move scores
+            // at least its negation
+            if matches!(entry.bound, Bound::Upper | Bound::Exact) && -entry.score >= beta {
+                return Some(-entry.score);
+            }
+        }
+        None
+    }
+
+    // The last few squares, where most nodes are, without the table or a
+    // move list
+    fn few_empties(&mut self, ally: u64, foe: u64, mut alpha: i32, beta: i32, passed: bool) -> i32 {
+        self.nodes += 1;
+        let empty = !(ally | foe);
+        if empty.count_ones() == 1 {
+            return last_square(ally, foe, empty);
+        }
+        let odd = odd_regions(empty);
+        let mut best = -BOUND;
+        for mut squares in [empty & odd, empty & !odd] {
+            while squares != 0 {
+                let position = squares & squares.wrapping_neg();
+                squares &= squares - 1;
+                let flipped = flips(ally, foe, position);
+                if flipped == 0 {
+                    continue;
+                }
+                let score = -self.few_empties(
+                    foe & !flipped,
+                    ally | flipped | position,
+                    -beta,
+                    -alpha,
+                    false,
+                );
+                if score > best {
+                    best = score;
+                    if score > alpha {
+                        alpha = score;
+                        if alpha >= beta {
+                            return best;
+                        }
+                    }
+                }
+            }
+        }
+        if best > -BOUND {
+            return best;
+        }
+        if passed {
+            return final_score(ally, foe);
+        }
+        -self.few_empties(foe, ally, -beta, -alpha, true)
+    }
+
+    fn negamax(&mut self, ally: u64, foe: u64, mut alpha: i32, beta: i32, passed: bool) -> i32 {
+        let empty = !(ally | foe);
+        if empty.count_ones() <= FEW_EMPTIES {
+            return self.few_empties(ally, foe, alpha, beta, passed);
+        }
+        self.nodes += 1;
+        let moves = legal_moves(ally, foe);
+        if moves == 0 {
+            if passed {
+                return final_score(ally, foe);
+            }
+            return -self.negamax(foe, ally, -beta, -alpha, true);
+        }
+
+        // Foe discs that can never flip cap the score, only worth counting
+        // when alpha is high enough for enough of them to cut
+        if alpha >= 64 - 2 * foe.count_ones() as i32 {
+            let best = 64 - 2 * stable_discs(foe, ally).count_ones() as i32;
+            if best <= alpha {
+                return best;
+            }
+        }
+
+        let empties = empty.count_ones();
+        let key = hash_bitboards(ally, foe);
+        let mut hash_move = 0;
+        if empties >= TABLE_EMPTIES {
+            if let Some(entry) = self.table.probe(key) {
+                hash_move = entry.best;
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => {}
+                }
+            }
+        }
+
+        if empties >= ETC_EMPTIES {
+            if let Some(score) = self.transposition_cutoff(ally, foe, moves, beta) {
+                return score;
+            }
+        }
+
+        let mut buffer = [0; 64];
+        let count = ordered_moves(ally, foe, moves, &mut buffer);
+        if let Some(index) = buffer[..count].iter().position(|&m| m == hash_move) {
+            buffer[..=index].rotate_right(1);
+        }
+        let original_alpha = alpha;
+        let mut best = -BOUND;
+        let mut best_move = 0;
+        for (index, &position) in buffer[..count].iter().enumerate() {
+            let flipped = flips(ally, foe, position);
+            let (ally, foe) = (ally | flipped | position, foe & !flipped);
+            // Later moves only need proving worse with a null window, unless they are not
+            let score = if index == 0 {
+                -self.negamax(foe, ally, -beta, -alpha, false)
+            } else {
+                let score = -self.negamax(foe, ally, -alpha - 1, -alpha, false);
+                if score > alpha && score < beta {
+                    -self.negamax(foe, ally, -beta, -score, false)
+                } else {
+                    score
+                }
+            };
+            if score > best {
+                best = score;
+                best_move = position;
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if empties >= TABLE_EMPTIES {
+            let bound = if best <= original_alpha {
+                Bound::Upper
+            } else if best >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.table.store(key, empties as u8, bound, best, best_move);
+        }
+        best
+    }
+}
+
+// Solves the position to the end of the game with the side to move as ally
+pub fn solve(ally: u64, foe: u64, mode: SolveMode) -> Solution {
+    solve_with_table(ally, foe, mode, &mut TranspositionTable::default())
+}
+
+// Solve reusing a table, which must not be shared with the heuristic search
+pub fn solve_with_table(
+    ally: u64,
+    foe: u64,
+    mode: SolveMode,
+    table: &mut TranspositionTable,
+) -> Solution {
+    let (mut alpha, beta) = match mode {
+        SolveMode::Exact => (-BOUND, BOUND),
+        SolveMode::WinLossDraw => (-1, 1),
+    };
+    table.new_search();
+    let mut solver = Solver { table, nodes: 1 };
+    let moves = legal_moves(ally, foe);
+    let (best, score) = if moves == 0 {
+        if legal_moves(foe, ally) == 0 {
+            (None, final_score(ally, foe))
+        } else {
+            let score = -solver.negamax(foe, ally, -beta, -alpha, true);
+            (Some(Action::Pass), score)
+        }
+    } else {
+        let mut buffer = [0; 64];
+        let count = ordered_moves(ally, foe, moves, &mut buffer);
+        let mut best = (buffer[0], -BOUND);
+        for (index, &position) in buffer[..count].iter().enumerate() {
+            let flipped = flips(ally, foe, position);
+            let (ally, foe) = (ally | flipped | position, foe & !flipped);
+            // As in the search below, only the first move gets the full window
+            let score = if index == 0 {
+                -solver.negamax(foe, ally, -beta, -alpha, false)
+            } else {
+                let score = -solver.negamax(foe, ally, -alpha - 1, -alpha, false);
+                if score > alpha && score < beta {
+                    -solver.negamax(foe, ally, -beta, -score, false)
+                } else {
+                    score
+                }
+            };
+            if score > best.1 {
+                best = (position, score);
+                alpha = alpha.max(score);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        (Some(Action::Place(best.0)), best.1)
+    };
+
+    let score = match mode {
+        SolveMode::Exact => score,
+        SolveMode::WinLossDraw => score.signum(),
+    };
+    Solution {
+        best,
+        score,
+        nodes: solver.nodes,
+    }
+}
+
+// Solves the game state from the side to move's point of view
+pub fn solve_state(state: &GameState, mode: SolveMode) -> Solution {
+    let (ally, foe) = state.config.ally_foe();
+    solve(ally, foe, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::bitboard_position;
+
+    // Exhaustive minimax without pruning or ordering
+    fn minimax(ally: u64, foe: u64) -> i32 {
+        let mut moves = legal_moves(ally, foe);
+        if moves == 0 {
+            if legal_moves(foe, ally) == 0 {
+                return final_score(ally, foe);
+            }
+            return -minimax(foe, ally);
+        }
+        let mut best = -BOUND;
+        while moves != 0 {
+            let position = moves & moves.wrapping_neg();
+            moves &= moves - 1;
+            let flipped = flips(ally, foe, position);
+            best = best.max(-minimax(foe & !flipped, ally | flipped | position));
+        }
+        best
+    }
+
+    // Plays first available moves from the opening until few squares are left
+    fn late_position(empties: u32, skip: usize) -> GameState {
+        let mut state = GameState::new();
+        let mut turn = 0;
+        while (!(state.config.white_pieces | state.config.black_pieces)).count_ones() > empties {
+            let moves = state.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            state = state.apply(moves[(turn * 7 + skip) % moves.len()]).unwrap();
+            turn += 1;
+        }
+        state
+    }
+
+    #[test]
+    fn test_matches_minimax() {
+        for skip in 0..6 {
+            let state = late_position(9, skip);
+            let (ally, foe) = state.config.ally_foe();
+            let exact = solve(ally, foe, SolveMode::Exact);
+            assert_eq!(exact.score, minimax(ally, foe));
+            let wld = solve(ally, foe, SolveMode::WinLossDraw);
+            assert_eq!(wld.score, exact.score.signum());
+        }
+    }
+
+    #[test]
+    fn test_best_move_reaches_score() {
+        let state = late_position(10, 3);
+        let solution = solve_state(&state, SolveMode::Exact);
+        let next = state.apply(solution.best.unwrap()).unwrap();
+        let (ally, foe) = next.config.ally_foe();
+        assert_eq!(-solve(ally, foe, SolveMode::Exact).score, solution.score);
+    }
+
+    // The top of the range the solver is meant for, too slow for debug
+    // builds: cargo test --release solver -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn test_late_endgames() {
+        for (empties, skip) in [(20, 0), (20, 1), (20, 2), (22, 0), (22, 1), (22, 2)] {
+            let state = late_position(empties, skip);
+            let start = Instant::now();
+            let solution = solve_state(&state, SolveMode::Exact);
+            println!(
+                "{empties} empties: {} in {:?}, {} nodes",
+                solution.score,
+                start.elapsed(),
+                solution.nodes
+            );
+            let next = state.apply(solution.best.unwrap()).unwrap();
+            let (ally, foe) = next.config.ally_foe();
+            assert_eq!(-solve(ally, foe, SolveMode::Exact).score, solution.score);
+            let wld = solve_state(&state, SolveMode::WinLossDraw);
+            assert_eq!(wld.score, solution.score.signum());
+        }
+    }
+
+    #[test]
+    fn test_finished_game() {
+        let ally = bitboard_position(0, 0) | bitboard_position(0, 1);
+        let foe = bitboard_position(7, 7);
+        let solution = solve(ally, foe, SolveMode::Exact);
+        assert_eq!(solution.best, None);
+        assert_eq!(solution.score, 62);
+    }
+}
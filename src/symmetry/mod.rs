@@ -0,0 +1,278 @@
+use crate::game::PieceConfig;
+
+// The 8 ways to turn or mirror the board into itself, with a1 in the top left
+// corner and rows 1 to 8 from the top
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Identity,
+    // Rows upside down, a1 to a8
+    FlipVertical,
+    // Columns mirrored, a1 to h1
+    MirrorHorizontal,
+    // Rows and columns swapped, a8 to h1 and the a1-h8 diagonal stays
+    FlipDiagonal,
+    // Mirrored in the h1-a8 diagonal, a1 to h8
+    FlipAntiDiagonal,
+    // Quarter turn clockwise, a1 to h1
+    Rotate90,
+    Rotate180,
+    // Quarter turn anticlockwise, a1 to a8
+    Rotate270,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::FlipVertical,
+        Symmetry::MirrorHorizontal,
+        Symmetry::FlipDiagonal,
+        Symmetry::FlipAntiDiagonal,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+    ];
+
+    pub fn apply(self, board: u64) -> u64 {
+        match self {
+            Symmetry::Identity => board,
+            Symmetry::FlipVertical => flip_vertical(board),
+            Symmetry::MirrorHorizontal => mirror_horizontal(board),
+            Symmetry::FlipDiagonal => flip_diagonal(board),
+            Symmetry::FlipAntiDiagonal => flip_anti_diagonal(board),
+            Symmetry::Rotate90 => rotate_90(board),
+            Symmetry::Rotate180 => rotate_180(board),
+            Symmetry::Rotate270 => rotate_270(board),
+        }
+    }
+
+    // Row and column a square moves to, rows and columns counted from a1
+    pub fn map_square(self, row: u8, column: u8) -> (u8, u8) {
+        match self {
+            Symmetry::Identity => (row, column),
+            Symmetry::FlipVertical => (7 - row, column),
+            Symmetry::MirrorHorizontal => (row, 7 - column),
+            Symmetry::FlipDiagonal => (column, row),
+            Symmetry::FlipAntiDiagonal => (7 - column, 7 - row),
+            Symmetry::Rotate90 => (column, 7 - row),
+            Symmetry::Rotate180 => (7 - row, 7 - column),
+            Symmetry::Rotate270 => (7 - column, row),
+        }
+    }
+
+    // Both sides' pieces moved, the side to move stays
+    pub fn apply_config(self, config: &PieceConfig) -> PieceConfig {
+        PieceConfig {
+            white_pieces: self.apply(config.white_pieces),
+            black_pieces: self.apply(config.black_pieces),
+            blacks_play: config.blacks_play,
+        }
+    }
+
+    // Symmetry undoing this one
+    pub fn inverse(self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            symmetry => symmetry,
+        }
+    }
+}
+
+// Rows upside down
+pub fn flip_vertical(board: u64) -> u64 {
+    board.swap_bytes()
+}
+
+// Columns mirrored
+pub fn mirror_horizontal(board: u64) -> u64 {
+    board.reverse_bits().swap_bytes()
+}
+
+// Rows and columns swapped by exchanging blocks of bits across the a1-h8
+// diagonal, first 4x4 blocks, then 2x2 blocks, then single squares
+pub fn flip_diagonal(mut board: u64) -> u64 {
+    for (shift, mask) in [
+        (28, 0x0f0f0f0f00000000),
+        (14, 0x3333000033330000),
+        (7, 0x5500550055005500),
+    ] {
+        let swapped = mask & (board ^ (board << shift));
+        board ^= swapped ^ (swapped >> shift);
+    }
+    board
+}
+
+// Mirrored in the h1-a8 diagonal
+pub fn flip_anti_diagonal(board: u64) -> u64 {
+    rotate_180(flip_diagonal(board))
+}
+
+// Quarter turn clockwise
+pub fn rotate_90(board: u64) -> u64 {
+    mirror_horizontal(flip_diagonal(board))
+}
+
+pub fn rotate_180(board: u64) -> u64 {
+    board.reverse_bits()
+}
+
+// Quarter turn anticlockwise
+pub fn rotate_270(board: u64) -> u64 {
+    flip_vertical(flip_diagonal(board))
+}
+
+// Smallest of the position's 8 symmetric forms, comparing black's pieces
+// first, and the symmetry leading to it. Symmetric positions share it
+pub fn canonical(config: &PieceConfig) -> (PieceConfig, Symmetry) {
+    Symmetry::ALL
+        .into_iter()
+        .map(|symmetry| (symmetry.apply_config(config), symmetry))
+        .min_by_key(|(form, _)| (form.black_pieces, form.white_pieces))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Action, GameState};
+    use crate::{bitboard_position, flips, legal_moves, stable_discs};
+
+    // Random positions reached by playing random moves from the start
+    fn random_positions(count: usize) -> Vec<PieceConfig> {
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        let mut positions = Vec::new();
+        let mut state = GameState::new();
+        while positions.len() < count {
+            positions.push(state.config);
+            let moves = state.legal_moves();
+            if moves.is_empty() {
+                state = GameState::new();
+                continue;
+            }
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            state = state.apply(moves[seed as usize % moves.len()]).unwrap();
+        }
+        positions
+    }
+
+    #[test]
+    fn test_squares() {
+        let a1 = bitboard_position(0, 0);
+        let b1 = bitboard_position(0, 1);
+        let expected = [
+            (Symmetry::Identity, 0, 0, 0, 1),
+            (Symmetry::FlipVertical, 7, 0, 7, 1),
+            (Symmetry::MirrorHorizontal, 0, 7, 0, 6),
+            (Symmetry::FlipDiagonal, 0, 0, 1, 0),
+            (Symmetry::FlipAntiDiagonal, 7, 7, 6, 7),
+            (Symmetry::Rotate90, 0, 7, 1, 7),
+            (Symmetry::Rotate180, 7, 7, 7, 6),
+            (Symmetry::Rotate270, 7, 0, 6, 0),
+        ];
+        for (symmetry, a1_row, a1_column, b1_row, b1_column) in expected {
+            assert_eq!(
+                symmetry.apply(a1),
+                bitboard_position(a1_row, a1_column),
+                "{symmetry:?}"
+            );
+            assert_eq!(
+                symmetry.apply(b1),
+                bitboard_position(b1_row, b1_column),
+                "{symmetry:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        let board = 0x0123456789abcdef;
+        for symmetry in Symmetry::ALL {
+            assert_eq!(symmetry.inverse().apply(symmetry.apply(board)), board);
+        }
+    }
+
+    #[test]
+    fn test_map_square() {
+        for symmetry in Symmetry::ALL {
+            for row in 0..8 {
+                for column in 0..8 {
+                    let (mapped_row, mapped_column) = symmetry.map_square(row, column);
+                    assert_eq!(
+                        symmetry.apply(bitboard_position(row, column)),
+                        bitboard_position(mapped_row, mapped_column),
+                        "{symmetry:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compositions() {
+        let board = 0x0123456789abcdef;
+        assert_eq!(rotate_90(rotate_90(board)), rotate_180(board));
+        assert_eq!(rotate_90(rotate_180(board)), rotate_270(board));
+        assert_eq!(flip_vertical(mirror_horizontal(board)), rotate_180(board));
+        for symmetry in [
+            flip_vertical,
+            mirror_horizontal,
+            flip_diagonal,
+            flip_anti_diagonal,
+        ] {
+            assert_eq!(symmetry(symmetry(board)), board);
+        }
+    }
+
+    #[test]
+    fn test_move_generation_commutes() {
+        for config in random_positions(500) {
+            let (ally, foe) = config.ally_foe();
+            let moves = legal_moves(ally, foe);
+            for symmetry in Symmetry::ALL {
+                let (turned_ally, turned_foe) = (symmetry.apply(ally), symmetry.apply(foe));
+                assert_eq!(legal_moves(turned_ally, turned_foe), symmetry.apply(moves));
+                assert_eq!(
+                    stable_discs(turned_ally, turned_foe),
+                    symmetry.apply(stable_discs(ally, foe))
+                );
+                let mut remaining = moves;
+                while remaining != 0 {
+                    let position = remaining & remaining.wrapping_neg();
+                    assert_eq!(
+                        flips(turned_ally, turned_foe, symmetry.apply(position)),
+                        symmetry.apply(flips(ally, foe, position))
+                    );
+                    remaining &= remaining - 1;
+                }
+                let state = GameState::from(config);
+                let turned = GameState::from(symmetry.apply_config(&config));
+                for action in state.legal_moves() {
+                    let turned_action = match action {
+                        Action::Place(position) => Action::Place(symmetry.apply(position)),
+                        Action::Pass => Action::Pass,
+                    };
+                    assert_eq!(
+                        turned.apply(turned_action).unwrap().config,
+                        symmetry.apply_config(&state.apply(action).unwrap().config)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        for config in random_positions(200) {
+            let (form, symmetry) = canonical(&config);
+            assert_eq!(symmetry.apply_config(&config), form);
+            for other in Symmetry::ALL {
+                assert_eq!(canonical(&other.apply_config(&config)).0, form);
+            }
+        }
+        // The start position is already in canonical form
+        let start = GameState::new().config;
+        assert_eq!(canonical(&start).0, start);
+    }
+}
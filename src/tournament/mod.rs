@@ -0,0 +1,376 @@
+use std::{fmt, io, path::PathBuf, process::Command, str::FromStr, thread, time::Duration};
+
+use crate::book::{Book, DEFAULT_PLIES};
+use crate::controller::{Move, Player};
+use crate::eval::Weights;
+use crate::game::{Action, GameState, Side};
+use crate::notation::parse_transcript;
+use crate::players::{AiPlayer, EnginePlayer};
+use crate::search::SearchLimits;
+
+// How long to wait before asking a player that has no move yet again
+const POLL_DELAY: Duration = Duration::from_millis(1);
+
+// Something that can play games, a fresh player is made for every game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Contestant {
+    Ai {
+        limits: SearchLimits,
+        weights: Option<PathBuf>,
+        // File of game transcripts to build an opening book from
+        book: Option<PathBuf>,
+    },
+    // Program and arguments of an engine speaking the engine protocol
+    Engine(Vec<String>),
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// Numbers that do not fit the setting are refused rather than wrapped
+fn number<T: FromStr>(value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid_input(format!("{value:?} is not a number in range")))
+}
+
+// Parses "ai", "ai:depth=6", "ai:time=500,weights=tuned.txt,book=games.txt"
+// with time in milliseconds, or "engine:<command line>"
+impl FromStr for Contestant {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<Contestant> {
+        let (kind, settings) = text.split_once(':').unwrap_or((text, ""));
+        match kind {
+            "ai" => {
+                let mut limits = SearchLimits::default();
+                let mut weights = None;
+                let mut book = None;
+                for setting in settings.split(',').filter(|s| !s.is_empty()) {
+                    let (key, value) = setting.split_once('=').ok_or_else(|| {
+                        invalid_input(format!("expected key=value in {setting:?}"))
+                    })?;
+                    match key {
+                        "depth" => limits.depth = number(value)?,
+                        "time" => {
+                            limits = SearchLimits::time(Duration::from_millis(number(value)?))
+                        }
+                        "weights" => weights = Some(PathBuf::from(value)),
+                        "book" => book = Some(PathBuf::from(value)),
+                        key => return Err(invalid_input(format!("unknown setting {key:?}"))),
+                    }
+                }
+                Ok(Contestant::Ai {
+                    limits,
+                    weights,
+                    book,
+                })
+            }
+            "engine" => {
+                let command: Vec<String> = settings.split_whitespace().map(String::from).collect();
+                if command.is_empty() {
+                    return Err(invalid_input("engine needs a command".to_string()));
+                }
+                Ok(Contestant::Engine(command))
+            }
+            kind => Err(invalid_input(format!("unknown contestant {kind:?}"))),
+        }
+    }
+}
+
+impl fmt::Display for Contestant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Contestant::Ai { limits, .. } => match limits.time {
+                Some(time) => write!(f, "ai {}ms", time.as_millis()),
+                None => write!(f, "ai depth {}", limits.depth),
+            },
+            Contestant::Engine(command) => write!(f, "{}", command.join(" ")),
+        }
+    }
+}
+
+impl Contestant {
+    pub fn create(&self) -> io::Result<Box<dyn Player>> {
+        match self {
+            Contestant::Ai {
+                limits,
+                weights,
+                book,
+            } => {
+                let mut player = AiPlayer::new(*limits);
+                if let Some(path) = weights {
+                    player = player.with_weights(Weights::from_file(path)?);
+                }
+                if let Some(path) = book {
+                    player = player.with_book(Book::from_file(path, DEFAULT_PLIES)?);
+                }
+                Ok(Box::new(player))
+            }
+            Contestant::Engine(command) => {
+                let mut process = Command::new(&command[0]);
+                process.args(&command[1..]);
+                Ok(Box::new(EnginePlayer::spawn(&mut process)?))
+            }
+        }
+    }
+}
+
+// One transcript per line, blank lines and lines starting with # are skipped
+pub fn parse_openings(text: &str) -> io::Result<Vec<Vec<Action>>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(number, line)| {
+            parse_transcript(line)
+                .map_err(|error| invalid_input(format!("opening on line {}: {error}", number + 1)))
+        })
+        .collect()
+}
+
+// Final disc counts, a player that failed to move loses by forfeit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub black: u32,
+    pub white: u32,
+    pub forfeit: Option<Side>,
+}
+
+impl Outcome {
+    pub fn winner(&self) -> Option<Side> {
+        if let Some(side) = self.forfeit {
+            return Some(side.opponent());
+        }
+        match self.black.cmp(&self.white) {
+            std::cmp::Ordering::Greater => Some(Side::Black),
+            std::cmp::Ordering::Less => Some(Side::White),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+// Plays a game without a board, the opening moves are announced to both
+// players like moves of their opponent
+pub fn play_game(black: &mut dyn Player, white: &mut dyn Player, opening: &[Action]) -> Outcome {
+    let mut state = GameState::new();
+    for &action in opening {
+        if let Action::Place(position) = action {
+            black.enemy_move(position);
+            white.enemy_move(position);
+        }
+        state = state.apply(action).expect("opening is not legal");
+    }
+    loop {
+        if state.must_pass() {
+            state = state.apply(Action::Pass).unwrap();
+        }
+        if state.is_terminal() {
+            let (black, white) = state.score();
+            return Outcome {
+                black,
+                white,
+                forfeit: None,
+            };
+        }
+        let side = state.side_to_move();
+        let (player, opponent): (&mut dyn Player, &mut dyn Player) = match side {
+            Side::Black => (&mut *black, &mut *white),
+            Side::White => (&mut *white, &mut *black),
+        };
+        let next = match player.play_move(&state.config) {
+            Move::Position(position) => {
+                opponent.enemy_move(position);
+                state.apply(Action::Place(position))
+            }
+            Move::Pending => {
+                thread::sleep(POLL_DELAY);
+                continue;
+            }
+            Move::Board | Move::Reset(_) => None,
+        };
+        let Some(next) = next else {
+            let (black, white) = state.score();
+            return Outcome {
+                black,
+                white,
+                forfeit: Some(side),
+            };
+        };
+        state = next;
+    }
+}
+
+// Results from the first contestant's point of view
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    // Sum of the first contestant's discs minus the second's
+    pub discs: i64,
+}
+
+// Two sided 95% quantile of the normal distribution
+const Z_95: f64 = 1.96;
+
+fn elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl MatchStats {
+    pub fn add(&mut self, outcome: &Outcome, first: Side) {
+        match outcome.winner() {
+            Some(side) if side == first => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+        let (black, white) = (outcome.black as i64, outcome.white as i64);
+        self.discs += match first {
+            Side::Black => black - white,
+            Side::White => white - black,
+        };
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    // Points per game with draws counting half
+    pub fn score(&self) -> Option<f64> {
+        let games = self.games();
+        (games > 0).then(|| (self.wins as f64 + self.draws as f64 / 2.0) / games as f64)
+    }
+
+    pub fn average_discs(&self) -> Option<f64> {
+        let games = self.games();
+        (games > 0).then(|| self.discs as f64 / games as f64)
+    }
+
+    // Elo difference with its 95% confidence interval, infinite when one
+    // side won every game
+    pub fn elo(&self) -> Option<(f64, f64, f64)> {
+        let score = self.score()?;
+        let games = self.games() as f64;
+        let deviation = |result: f64, count: u32| count as f64 * (result - score).powi(2);
+        let variance =
+            (deviation(1.0, self.wins) + deviation(0.5, self.draws) + deviation(0.0, self.losses))
+                / games;
+        let margin = Z_95 * (variance / games).sqrt();
+        let bound = |score: f64| elo(score.clamp(0.0, 1.0));
+        Some((elo(score), bound(score - margin), bound(score + margin)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_contestant() {
+        assert_eq!(
+            "ai".parse::<Contestant>().unwrap(),
+            Contestant::Ai {
+                limits: SearchLimits::default(),
+                weights: None,
+                book: None
+            }
+        );
+        assert_eq!(
+            "ai:time=250,weights=w.txt,book=games.txt"
+                .parse::<Contestant>()
+                .unwrap(),
+            Contestant::Ai {
+                limits: SearchLimits::time(Duration::from_millis(250)),
+                weights: Some(PathBuf::from("w.txt")),
+                book: Some(PathBuf::from("games.txt"))
+            }
+        );
+        assert_eq!(
+            "engine:./bot --level 3".parse::<Contestant>().unwrap(),
+            Contestant::Engine(vec!["./bot".into(), "--level".into(), "3".into()])
+        );
+        assert_eq!(
+            "ai:depth=12".parse::<Contestant>().unwrap(),
+            Contestant::Ai {
+                limits: SearchLimits::depth(12),
+                weights: None,
+                book: None
+            }
+        );
+        assert!("ai:depth=deep".parse::<Contestant>().is_err());
+        assert!("ai:depth=256".parse::<Contestant>().is_err());
+        assert!("ai:speed=1".parse::<Contestant>().is_err());
+        assert!("engine:".parse::<Contestant>().is_err());
+        assert!("human".parse::<Contestant>().is_err());
+    }
+
+    #[test]
+    fn test_play_game() {
+        let opening = parse_transcript("f5d6c3").unwrap();
+        let mut black = AiPlayer::new(SearchLimits::depth(1));
+        let mut white = AiPlayer::new(SearchLimits::depth(2));
+        let outcome = play_game(&mut black, &mut white, &opening);
+        assert_eq!(outcome.forfeit, None);
+        assert!(outcome.black + outcome.white <= 64);
+    }
+
+    #[test]
+    fn test_parse_openings() {
+        let openings = parse_openings("# tiger\nf5d6c3d3c4\n\nf5f6\n").unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[1], parse_transcript("f5f6").unwrap());
+        assert!(parse_openings("f5f5").is_err());
+    }
+
+    struct Stuck {}
+
+    impl Player for Stuck {
+        fn play_move(&mut self, _config: &crate::game::PieceConfig) -> Move {
+            Move::Board
+        }
+
+        fn enemy_move(&mut self, _current_move: u64) {}
+    }
+
+    #[test]
+    fn test_forfeit() {
+        let mut black = AiPlayer::new(SearchLimits::depth(1));
+        let outcome = play_game(&mut black, &mut Stuck {}, &[]);
+        assert_eq!(outcome.forfeit, Some(Side::White));
+        assert_eq!(outcome.winner(), Some(Side::Black));
+        assert_eq!((outcome.black, outcome.white), (4, 1));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = MatchStats::default();
+        let win = Outcome {
+            black: 40,
+            white: 24,
+            forfeit: None,
+        };
+        let draw = Outcome {
+            black: 32,
+            white: 32,
+            forfeit: None,
+        };
+        stats.add(&win, Side::Black);
+        stats.add(&win, Side::White);
+        stats.add(&draw, Side::White);
+        stats.add(&win, Side::Black);
+        assert_eq!((stats.wins, stats.losses, stats.draws), (2, 1, 1));
+        assert_eq!(stats.average_discs(), Some(4.0));
+        assert_eq!(stats.score(), Some(0.625));
+        let (estimate, low, high) = stats.elo().unwrap();
+        assert!((estimate - 88.7).abs() < 0.1);
+        assert!(low < estimate && estimate < high);
+
+        // Even results are no difference at all
+        let mut even = MatchStats::default();
+        even.add(&win, Side::Black);
+        even.add(&win, Side::White);
+        assert_eq!(even.elo().unwrap().0, 0.0);
+        assert_eq!(MatchStats::default().elo(), None);
+    }
+}
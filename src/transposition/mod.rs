@@ -0,0 +1,268 @@
+use crate::game::PieceConfig;
+
+// Memory budget used when callers do not pick one
+pub const DEFAULT_MEMORY: usize = 16 * 1024 * 1024;
+
+const fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// Zobrist keys per color and square, pre-combined for every value of each
+// byte of a bitboard so hashing takes 16 lookups instead of one per piece
+const fn byte_keys() -> [[[u64; 256]; 8]; 2] {
+    let mut square_keys = [[0; 64]; 2];
+    let mut seed = 0x2545F4914F6CDD1D;
+    let mut color = 0;
+    while color < 2 {
+        let mut square = 0;
+        while square < 64 {
+            seed = splitmix64(seed);
+            square_keys[color][square] = seed;
+            square += 1;
+        }
+        color += 1;
+    }
+
+    let mut keys = [[[0; 256]; 8]; 2];
+    let mut color = 0;
+    while color < 2 {
+        let mut byte = 0;
+        while byte < 8 {
+            let mut value = 0;
+            while value < 256 {
+                let mut key = 0;
+                let mut bit = 0;
+                while bit < 8 {
+                    if value & (1 << bit) != 0 {
+                        key ^= square_keys[color][byte * 8 + bit];
+                    }
+                    bit += 1;
+                }
+                keys[color][byte][value] = key;
+                value += 1;
+            }
+            byte += 1;
+        }
+        color += 1;
+    }
+    keys
+}
+
+static BYTE_KEYS: [[[u64; 256]; 8]; 2] = byte_keys();
+const WHITE_TO_MOVE: u64 = splitmix64(0x9FB21C651E98DF25);
+
+fn hash_bitboard(color: usize, board: u64) -> u64 {
+    board
+        .to_le_bytes()
+        .iter()
+        .enumerate()
+        .fold(0, |hash, (byte, &value)| {
+            hash ^ BYTE_KEYS[color][byte][value as usize]
+        })
+}
+
+// Hash of a position seen from the side to move, positions with the same
+// ally and foe pieces share a value whichever color is playing
+pub fn hash_bitboards(ally: u64, foe: u64) -> u64 {
+    hash_bitboard(0, ally) ^ hash_bitboard(1, foe)
+}
+
+// Hash of both colors' pieces and the side to move
+pub fn hash(config: &PieceConfig) -> u64 {
+    let hash = hash_bitboards(config.black_pieces, config.white_pieces);
+    if config.blacks_play {
+        hash
+    } else {
+        hash ^ WHITE_TO_MOVE
+    }
+}
+
+// How the stored score relates to the true value of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // True value is at least the score, the search failed high
+    Lower,
+    // True value is at most the score, the search failed low
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    // Best placement found, 0 if there was none
+    pub best: u64,
+    generation: u8,
+}
+
+const EMPTY: Entry = Entry {
+    key: 0,
+    depth: 0,
+    bound: Bound::Upper,
+    score: 0,
+    best: 0,
+    generation: 0,
+};
+
+// Fixed size hash table of two entry buckets, the first slot keeps the deepest
+// result of the current search and the second always takes the newest one
+pub struct TranspositionTable {
+    entries: Vec<Entry>,
+    mask: usize,
+    generation: u8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::with_memory(DEFAULT_MEMORY)
+    }
+}
+
+impl TranspositionTable {
+    // Largest power of two bucket count fitting within the given bytes
+    pub fn with_memory(bytes: usize) -> TranspositionTable {
+        let buckets = (bytes / (2 * std::mem::size_of::<Entry>())).max(1);
+        let buckets = if buckets.is_power_of_two() {
+            buckets
+        } else {
+            buckets.next_power_of_two() / 2
+        };
+        TranspositionTable {
+            entries: vec![EMPTY; buckets * 2],
+            mask: buckets - 1,
+            generation: 1,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(EMPTY);
+        self.generation = 1;
+    }
+
+    // Marks entries from earlier searches as replaceable
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1).max(1);
+    }
+
+    fn bucket(&self, key: u64) -> usize {
+        (key as usize & self.mask) * 2
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let bucket = self.bucket(key);
+        self.entries[bucket..bucket + 2]
+            .iter()
+            .find(|entry| entry.generation != 0 && entry.key == key)
+            .copied()
+    }
+
+    pub fn store(&mut self, key: u64, depth: u8, bound: Bound, score: i32, best: u64) {
+        let bucket = self.bucket(key);
+        let entry = Entry {
+            key,
+            depth,
+            bound,
+            score,
+            best,
+            generation: self.generation,
+        };
+        let deepest = self.entries[bucket];
+        let replaces_deepest =
+            deepest.generation != self.generation || deepest.key == key || depth >= deepest.depth;
+        if replaces_deepest {
+            // Demote the previous deepest entry instead of losing it
+            if deepest.generation != 0 && deepest.key != key {
+                self.entries[bucket + 1] = deepest;
+            }
+            self.entries[bucket] = entry;
+        } else {
+            self.entries[bucket + 1] = entry;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+
+    // Zobrist hash xoring one key per piece
+    fn square_hash(color: usize, board: u64) -> u64 {
+        (0..64)
+            .filter(|square| board & (1 << square) != 0)
+            .fold(0, |hash, square| {
+                hash ^ BYTE_KEYS[color][square / 8][1 << (square % 8)]
+            })
+    }
+
+    #[test]
+    fn test_hash_is_zobrist() {
+        let state = GameState::new();
+        let next = state.legal_moves()[0];
+        let config = state.apply(next).unwrap().config;
+        assert_eq!(
+            hash(&config),
+            square_hash(0, config.black_pieces)
+                ^ square_hash(1, config.white_pieces)
+                ^ WHITE_TO_MOVE
+        );
+        assert_ne!(hash(&state.config), hash(&config));
+    }
+
+    #[test]
+    fn test_side_to_move_changes_hash() {
+        let mut config = GameState::new().config;
+        let black = hash(&config);
+        assert_eq!(
+            black,
+            hash_bitboards(config.black_pieces, config.white_pieces)
+        );
+        config.blacks_play = false;
+        assert_ne!(black, hash(&config));
+    }
+
+    #[test]
+    fn test_probe_and_store() {
+        let mut table = TranspositionTable::with_memory(1024);
+        assert!(table.capacity().is_power_of_two());
+        assert!(table.capacity() * std::mem::size_of::<Entry>() <= 1024);
+        assert_eq!(table.probe(42), None);
+
+        table.store(42, 3, Bound::Exact, 7, 1);
+        let entry = table.probe(42).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score, entry.best),
+            (3, Bound::Exact, 7, 1)
+        );
+
+        table.clear();
+        assert_eq!(table.probe(42), None);
+    }
+
+    #[test]
+    fn test_replacement_keeps_deepest() {
+        let mut table = TranspositionTable::with_memory(2 * std::mem::size_of::<Entry>());
+        table.store(1, 8, Bound::Exact, 1, 0);
+        table.store(2, 2, Bound::Lower, 2, 0);
+        table.store(3, 1, Bound::Upper, 3, 0);
+        assert_eq!(table.probe(1).unwrap().depth, 8);
+        assert_eq!(table.probe(2), None);
+        assert_eq!(table.probe(3).unwrap().score, 3);
+
+        // Shallower results replace the deepest slot once a new search starts
+        table.new_search();
+        table.store(4, 1, Bound::Exact, 4, 0);
+        assert_eq!(table.probe(4).unwrap().score, 4);
+        assert_eq!(table.probe(1).unwrap().depth, 8);
+    }
+}